@@ -0,0 +1,194 @@
+//! Plays in a terminal with ANSI colours, no window or font needed, so it
+//! works over SSH. Needs a terminal of at least 80x52 with true colour.
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind};
+use crossterm::style::{Color as TermColor, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use roguelike::colors::*;
+use roguelike::options::Options;
+use roguelike::render::{main_menu, watch_replay, Buffer, Input, KeyEvent, KeyPress, Renderer, Screen, PANEL_HEIGHT};
+use roguelike::*;
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+// the map with the panel right under it
+const SCREEN_WIDTH: i32 = MAP_WIDTH;
+const SCREEN_HEIGHT: i32 = MAP_HEIGHT + PANEL_HEIGHT;
+
+fn term_color( color: Color ) -> TermColor {
+    TermColor::Rgb { r: color.r, g: color.g, b: color.b }
+}
+
+/// Draws into a `Buffer` and writes it out on `present`. Puts the terminal
+/// back however the game ends, panics included. Once writing or reading
+/// fails every key reads as Escape, which winds the game down.
+struct Terminal {
+    out: Stdout,
+    screen: Buffer,
+    failed: bool,
+}
+
+impl Terminal {
+    fn new() -> io::Result<Self> {
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, EnableMouseCapture, Hide)?;
+        Ok(Terminal { out, screen: Buffer::new(SCREEN_WIDTH, SCREEN_HEIGHT), failed: false })
+    }
+
+    fn draw( &mut self ) -> io::Result<()> {
+        let mut fg = None;
+        let mut bg = None;
+        for y in 0..SCREEN_HEIGHT {
+            queue!(self.out, MoveTo(0, y as u16))?;
+            for x in 0..SCREEN_WIDTH {
+                let cell = self.screen.cell(x, y).unwrap();
+                // only send colours when they change, it keeps SSH traffic down
+                if fg != Some(cell.fg) {
+                    queue!(self.out, SetForegroundColor(term_color(cell.fg)))?;
+                    fg = Some(cell.fg);
+                }
+                if bg != Some(cell.bg) {
+                    queue!(self.out, SetBackgroundColor(term_color(cell.bg)))?;
+                    bg = Some(cell.bg);
+                }
+                queue!(self.out, Print(cell.ch))?;
+            }
+        }
+        queue!(self.out, ResetColor)?;
+        self.out.flush()
+    }
+
+    /// Escape once reading has failed, so the screens wind down.
+    fn poll( &mut self, timeout: Option<Duration> ) -> Option<Input> {
+        if self.failed {
+            return Some(Input::Key(KeyPress::Escape.into()));
+        }
+        self.read_input(timeout).unwrap_or_else(|_| {
+            self.failed = true;
+            Some(Input::Key(KeyPress::Escape.into()))
+        })
+    }
+
+    /// The next input, `None` if there is none before `timeout`.
+    fn read_input( &mut self, timeout: Option<Duration> ) -> io::Result<Option<Input>> {
+        let started = Instant::now();
+        loop {
+            if let Some(timeout) = timeout {
+                if !event::poll(timeout.saturating_sub(started.elapsed()))? {
+                    return Ok(None);
+                }
+            }
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => return Ok(Some(Input::Key(key_event(key)))),
+                Event::Mouse(mouse) => {
+                    let (left, right) = match mouse.kind {
+                        MouseEventKind::Down(MouseButton::Left) => (true, false),
+                        MouseEventKind::Down(MouseButton::Right) => (false, true),
+                        MouseEventKind::Moved | MouseEventKind::Drag(_) => (false, false),
+                        _ => continue,
+                    };
+                    let (x, y) = (mouse.column as i32, mouse.row as i32);
+                    return Ok(Some(Input::Mouse { x, y, left, right }));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The same key as the shared screens and key bindings see it.
+fn key_event( key: event::KeyEvent ) -> KeyEvent {
+    let press = match key.code {
+        KeyCode::Char(c) => KeyPress::Char(c),
+        KeyCode::Enter => KeyPress::Enter,
+        KeyCode::Esc => KeyPress::Escape,
+        KeyCode::Backspace => KeyPress::Backspace,
+        KeyCode::Tab => KeyPress::Tab,
+        KeyCode::Insert => KeyPress::Insert,
+        KeyCode::Delete => KeyPress::Delete,
+        KeyCode::Up => KeyPress::Up,
+        KeyCode::Down => KeyPress::Down,
+        KeyCode::Left => KeyPress::Left,
+        KeyCode::Right => KeyPress::Right,
+        KeyCode::Home => KeyPress::Home,
+        KeyCode::End => KeyPress::End,
+        KeyCode::PageUp => KeyPress::PageUp,
+        KeyCode::PageDown => KeyPress::PageDown,
+        KeyCode::F(n) => KeyPress::F(n),
+        _ => KeyPress::Other,
+    };
+    KeyEvent {
+        key: press,
+        alt: key.modifiers.contains(KeyModifiers::ALT),
+        ctrl: key.modifiers.contains(KeyModifiers::CONTROL),
+    }
+}
+
+impl Renderer for Terminal {
+    fn width( &self ) -> i32 {
+        SCREEN_WIDTH
+    }
+
+    fn height( &self ) -> i32 {
+        SCREEN_HEIGHT
+    }
+
+    fn clear( &mut self ) {
+        self.screen.clear();
+    }
+
+    fn put_char( &mut self, x: i32, y: i32, ch: char, fg: Color ) {
+        self.screen.put_char(x, y, ch, fg);
+    }
+
+    fn set_background( &mut self, x: i32, y: i32, bg: Color ) {
+        self.screen.set_background(x, y, bg);
+    }
+
+    fn background( &self, x: i32, y: i32 ) -> Color {
+        self.screen.background(x, y)
+    }
+
+    fn present( &mut self ) {
+        if !self.failed && self.draw().is_err() {
+            self.failed = true;
+        }
+    }
+
+    fn wait_for_input( &mut self ) -> Input {
+        self.poll(None).unwrap_or(Input::Key(KeyPress::Escape.into()))
+    }
+
+    fn poll_input( &mut self, timeout: Duration ) -> Option<Input> {
+        self.poll(Some(timeout))
+    }
+
+    fn closed( &self ) -> bool {
+        self.failed
+    }
+}
+
+impl Drop for Terminal {
+    fn drop( &mut self ) {
+        let _ = execute!(self.out, ResetColor, Show, DisableMouseCapture, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn main() -> io::Result<()> {
+    let options = Options::from_args();
+    let (columns, rows) = terminal::size()?;
+    if (columns as i32) < SCREEN_WIDTH || (rows as i32) < SCREEN_HEIGHT {
+        eprintln!("The terminal needs to be at least {}x{}, it is {}x{}.", SCREEN_WIDTH, SCREEN_HEIGHT, columns, rows);
+        return Ok(());
+    }
+    let mut screen = Screen { renderer: Terminal::new()?, options };
+    match screen.options.replay.clone() {
+        Some(path) => watch_replay(&mut screen, &path),
+        None => main_menu(&mut screen),
+    }
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! Plain RGB colours, so the simulation does not need tcod to describe how
+//! things look. The named colours have the same values as tcod's.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone,Copy,Debug,Default,PartialEq,Serialize,Deserialize)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn new( r: u8, g: u8, b: u8 ) -> Self {
+        Color { r, g, b }
+    }
+}
+
+#[cfg(feature = "tcod")]
+impl From<Color> for tcod::colors::Color {
+    fn from( color: Color ) -> Self {
+        tcod::colors::Color::new(color.r, color.g, color.b)
+    }
+}
+
+#[cfg(feature = "tcod")]
+impl From<tcod::colors::Color> for Color {
+    fn from( color: tcod::colors::Color ) -> Self {
+        Color::new(color.r, color.g, color.b)
+    }
+}
+
+pub const BLACK: Color = Color::new(0, 0, 0);
+pub const DARK_GREY: Color = Color::new(95, 95, 95);
+pub const LIGHT_GREY: Color = Color::new(159, 159, 159);
+pub const WHITE: Color = Color::new(255, 255, 255);
+
+pub const RED: Color = Color::new(255, 0, 0);
+pub const ORANGE: Color = Color::new(255, 127, 0);
+pub const YELLOW: Color = Color::new(255, 255, 0);
+pub const GREEN: Color = Color::new(0, 255, 0);
+pub const SKY: Color = Color::new(0, 191, 255);
+pub const VIOLET: Color = Color::new(127, 0, 255);
+
+pub const DARK_RED: Color = Color::new(191, 0, 0);
+pub const DARK_BLUE: Color = Color::new(0, 0, 191);
+
+pub const LIGHT_RED: Color = Color::new(255, 63, 63);
+pub const LIGHT_YELLOW: Color = Color::new(255, 255, 63);
+pub const LIGHT_GREEN: Color = Color::new(63, 255, 63);
+pub const LIGHT_BLUE: Color = Color::new(63, 63, 255);
+pub const LIGHT_VIOLET: Color = Color::new(159, 63, 255);
+
+pub const LIGHTER_BLUE: Color = Color::new(127, 127, 255);
@@ -0,0 +1,374 @@
+//! Everything in the dungeon is an `Entity`, a handle that stays valid for as
+//! long as the thing it names exists. What an entity is made of lives in one
+//! `Storage` per component on the `World`, so an item on the floor has a
+//! position and a carried one does not, and only monsters have an `Ai`.
+
+use crate::status::Effect;
+use crate::{Ai, Equipment, Fighter, Item, Object, MAP_HEIGHT, MAP_WIDTH};
+use serde::{Deserialize, Serialize};
+use std::ops::{Deref, Index, IndexMut};
+
+/// Names one entity. Once it is despawned the slot may be reused, but the
+/// generation moves on so old handles stop matching anything.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash,Serialize,Deserialize)]
+pub struct Entity {
+    pub(crate) index: u32,
+    pub(crate) generation: u32,
+}
+
+/// One component for any number of entities, indexed by entity.
+#[derive(Clone,Debug,Serialize,Deserialize)]
+pub struct Storage<T> {
+    slots: Vec<Option<(u32,T)>>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Storage { slots: vec![] }
+    }
+}
+
+impl<T> Storage<T> {
+    pub fn get( &self, entity: Entity ) -> Option<&T> {
+        match self.slots.get(entity.index as usize) {
+            Some(Some((generation, value))) if *generation == entity.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut( &mut self, entity: Entity ) -> Option<&mut T> {
+        match self.slots.get_mut(entity.index as usize) {
+            Some(Some((generation, value))) if *generation == entity.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn contains( &self, entity: Entity ) -> bool {
+        self.get(entity).is_some()
+    }
+
+    /// Attaches `value` to `entity`, handing back what it replaced.
+    pub fn insert( &mut self, entity: Entity, value: T ) -> Option<T> {
+        let index = entity.index as usize;
+        if index >= self.slots.len() {
+            self.slots.resize_with(index+1, || None);
+        }
+        match self.slots[index].replace((entity.generation, value)) {
+            Some((generation, old)) if generation == entity.generation => Some(old),
+            _ => None,
+        }
+    }
+
+    pub fn remove( &mut self, entity: Entity ) -> Option<T> {
+        self.get(entity)?;
+        self.slots[entity.index as usize].take().map(|(_, value)| value)
+    }
+
+    /// Every entity with this component, oldest slot first.
+    pub fn iter( &self ) -> impl Iterator<Item = (Entity, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.as_ref().map(|(generation, value)| {
+                (Entity { index: index as u32, generation: *generation }, value)
+            })
+        })
+    }
+
+    pub fn iter_mut( &mut self ) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(index, slot)| {
+            slot.as_mut().map(|(generation, value)| {
+                (Entity { index: index as u32, generation: *generation }, value)
+            })
+        })
+    }
+
+    pub fn entities( &self ) -> impl Iterator<Item = Entity> + '_ {
+        self.iter().map(|(entity, _)| entity)
+    }
+}
+
+impl<T> Index<Entity> for Storage<T> {
+    type Output = T;
+
+    fn index( &self, entity: Entity ) -> &T {
+        self.get(entity).expect("entity has the component")
+    }
+}
+
+impl<T> IndexMut<Entity> for Storage<T> {
+    fn index_mut( &mut self, entity: Entity ) -> &mut T {
+        self.get_mut(entity).expect("entity has the component")
+    }
+}
+
+/// The entities that have both components, with both of them.
+pub fn join<'a, A, B>( first: &'a Storage<A>, second: &'a Storage<B> ) -> impl Iterator<Item = (Entity, &'a A, &'a B)> {
+    first.iter().filter_map(move |(entity, a)| second.get(entity).map(|b| (entity, a, b)))
+}
+
+/// Where things are on the map, also indexed by tile so finding what stands
+/// somewhere does not mean looking at everything. It reads like any other
+/// `Storage`, but moves go through `insert` to keep the two in step.
+#[derive(Clone,Debug,Serialize,Deserialize)]
+#[serde(from = "SavedPositions", into = "SavedPositions")]
+pub struct Positions {
+    storage: Storage<(i32,i32)>,
+    // entities on each tile, in the order they arrived
+    tiles: Vec<Vec<Entity>>,
+}
+
+fn tile_index( (x,y): (i32,i32) ) -> Option<usize> {
+    let on_map = (0..MAP_WIDTH).contains(&x) && (0..MAP_HEIGHT).contains(&y);
+    if on_map {
+        Some((x * MAP_HEIGHT + y) as usize)
+    } else {
+        None
+    }
+}
+
+impl Default for Positions {
+    fn default() -> Self {
+        Positions {
+            storage: Storage::default(),
+            tiles: vec![vec![]; (MAP_WIDTH * MAP_HEIGHT) as usize],
+        }
+    }
+}
+
+/// How `Positions` is saved: the plain storage, plus everything on the map
+/// tile by tile so each tile keeps the order things arrived in. Saves from
+/// before `arrivals` fall back to slot order.
+#[derive(Serialize,Deserialize)]
+struct SavedPositions {
+    #[serde(flatten)]
+    storage: Storage<(i32,i32)>,
+    #[serde(default)]
+    arrivals: Vec<Entity>,
+}
+
+impl From<SavedPositions> for Positions {
+    fn from( saved: SavedPositions ) -> Self {
+        let mut positions = Positions::default();
+        for &entity in &saved.arrivals {
+            if let Some(&pos) = saved.storage.get(entity) {
+                positions.insert(entity, pos);
+            }
+        }
+        for (entity, &pos) in saved.storage.iter() {
+            if !positions.contains(entity) {
+                positions.insert(entity, pos);
+            }
+        }
+        positions
+    }
+}
+
+impl From<Positions> for SavedPositions {
+    fn from( positions: Positions ) -> Self {
+        SavedPositions {
+            arrivals: positions.tiles.concat(),
+            storage: positions.storage,
+        }
+    }
+}
+
+impl Deref for Positions {
+    type Target = Storage<(i32,i32)>;
+
+    fn deref( &self ) -> &Storage<(i32,i32)> {
+        &self.storage
+    }
+}
+
+impl Positions {
+    /// Puts `entity` at `pos`, moving it if it was already on the map.
+    pub fn insert( &mut self, entity: Entity, pos: (i32,i32) ) -> Option<(i32,i32)> {
+        let old = self.remove(entity);
+        self.storage.insert(entity, pos);
+        if let Some(index) = tile_index(pos) {
+            self.tiles[index].push(entity);
+        }
+        old
+    }
+
+    pub fn remove( &mut self, entity: Entity ) -> Option<(i32,i32)> {
+        let pos = self.storage.remove(entity)?;
+        if let Some(index) = tile_index(pos) {
+            self.tiles[index].retain(|&other| other != entity);
+        }
+        Some(pos)
+    }
+
+    /// Everything standing on `pos`.
+    pub fn at( &self, pos: (i32,i32) ) -> &[Entity] {
+        match tile_index(pos) {
+            Some(index) => &self.tiles[index],
+            None => &[],
+        }
+    }
+}
+
+/// Every entity in the game and all of their components. Each entity has an
+/// `Object`, the rest are optional.
+#[derive(Debug,Default,Serialize,Deserialize)]
+pub struct World {
+    generations: Vec<u32>,
+    free: Vec<u32>,
+    pub objects: Storage<Object>,
+    /// Only things on the map have one, carried items do not.
+    pub positions: Positions,
+    pub fighters: Storage<Fighter>,
+    pub ais: Storage<Ai>,
+    pub items: Storage<Item>,
+    pub equipment: Storage<Equipment>,
+    /// Effects wearing off on a creature, see `status`.
+    #[serde(default)]
+    pub statuses: Storage<Vec<Effect>>,
+    /// Marks the way down to the next level.
+    #[serde(default)]
+    pub stairs: Storage<()>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn( &mut self, object: Object ) -> Entity {
+        let entity = match self.free.pop() {
+            Some(index) => Entity { index, generation: self.generations[index as usize] },
+            None => {
+                self.generations.push(0);
+                Entity { index: self.generations.len() as u32 - 1, generation: 0 }
+            }
+        };
+        self.objects.insert(entity, object);
+        entity
+    }
+
+    /// Removes the entity with all of its components, `false` if it was
+    /// already gone.
+    pub fn despawn( &mut self, entity: Entity ) -> bool {
+        if self.objects.remove(entity).is_none() {
+            return false;
+        }
+        self.positions.remove(entity);
+        self.fighters.remove(entity);
+        self.ais.remove(entity);
+        self.items.remove(entity);
+        self.equipment.remove(entity);
+        self.statuses.remove(entity);
+        self.stairs.remove(entity);
+        self.generations[entity.index as usize] += 1;
+        self.free.push(entity.index);
+        true
+    }
+
+    pub fn contains( &self, entity: Entity ) -> bool {
+        self.objects.contains(entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::WHITE;
+    use crate::DeathCallback;
+
+    fn thing( world: &mut World, name: &str ) -> Entity {
+        world.spawn(Object::new('t', name, WHITE, false))
+    }
+
+    #[test]
+    fn freed_slots_are_reused_under_a_new_generation() {
+        let mut world = World::new();
+        let a = thing(&mut world, "a");
+        let b = thing(&mut world, "b");
+        world.fighters.insert(a, Fighter {
+            base_max_hp: 1,
+            hp: 1,
+            base_defense: 0,
+            base_power: 0,
+            xp: 0,
+            level: 1,
+            on_death: DeathCallback::Monster,
+            on_hit: None,
+        });
+        assert!(world.despawn(a));
+        assert!(!world.despawn(a));
+        assert!(!world.contains(a));
+
+        let c = thing(&mut world, "c");
+        assert_eq!(c.index, a.index);
+        assert_eq!(c.generation, a.generation + 1);
+        assert_ne!(c, a);
+        // the old handle sees nothing of the new entity or of its own components
+        assert!(world.objects.get(a).is_none());
+        assert_eq!(world.objects[c].name, "c");
+        assert!(world.fighters.get(c).is_none());
+        assert!(world.despawn(c));
+        assert!(world.contains(b));
+
+        let d = thing(&mut world, "d");
+        assert_eq!((d.index, d.generation), (a.index, a.generation + 2));
+        let e = thing(&mut world, "e");
+        assert_eq!(e.index, 2);
+    }
+
+    #[test]
+    fn moving_keeps_the_tile_index_in_step() {
+        let mut world = World::new();
+        let a = thing(&mut world, "a");
+        let b = thing(&mut world, "b");
+        assert_eq!(world.positions.insert(a, (3,4)), None);
+        world.positions.insert(b, (3,4));
+        assert_eq!(world.positions.at((3,4)), &[a, b]);
+
+        assert_eq!(world.positions.insert(a, (5,6)), Some((3,4)));
+        assert_eq!(world.positions[a], (5,6));
+        assert_eq!(world.positions.at((3,4)), &[b]);
+        assert_eq!(world.positions.at((5,6)), &[a]);
+
+        assert_eq!(world.positions.remove(b), Some((3,4)));
+        assert_eq!(world.positions.remove(b), None);
+        assert!(world.positions.at((3,4)).is_empty());
+        assert!(world.positions.at((-1,0)).is_empty());
+    }
+
+    #[test]
+    fn despawning_takes_the_entity_off_its_tile() {
+        let mut world = World::new();
+        let a = thing(&mut world, "a");
+        world.positions.insert(a, (1,1));
+        assert!(world.despawn(a));
+        assert!(!world.positions.contains(a));
+        assert!(world.positions.at((1,1)).is_empty());
+    }
+
+    #[test]
+    fn loading_rebuilds_the_tile_index() {
+        let mut world = World::new();
+        let a = thing(&mut world, "a");
+        let b = thing(&mut world, "b");
+        let gone = thing(&mut world, "gone");
+        // b arrives first, so the tile's order is not the order of the slots
+        world.positions.insert(b, (2,2));
+        world.positions.insert(a, (2,2));
+        world.positions.insert(gone, (7,7));
+        world.despawn(gone);
+        let c = thing(&mut world, "c");
+        world.positions.insert(c, (MAP_WIDTH-1, MAP_HEIGHT-1));
+
+        let json = serde_json::to_string(&world).unwrap();
+        let loaded: World = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.positions.at((2,2)), &[b, a]);
+        assert!(loaded.positions.at((7,7)).is_empty());
+        assert_eq!(loaded.positions.at((MAP_WIDTH-1, MAP_HEIGHT-1)), &[c]);
+        assert_eq!(loaded.positions.iter().count(), 3);
+
+        // a save from before the order was kept still loads, in slot order
+        let mut old: serde_json::Value = serde_json::from_str(&json).unwrap();
+        old["positions"].as_object_mut().unwrap().remove("arrivals");
+        let loaded: World = serde_json::from_value(old).unwrap();
+        assert_eq!(loaded.positions.at((2,2)), &[a, b]);
+    }
+}
@@ -0,0 +1,180 @@
+//! Field of view by ray casting: a line is drawn from the viewer to every tile
+//! on the edge of its sight, and each line stops at the first tile that blocks
+//! sight. The same "basic" algorithm tcod uses, without needing tcod.
+
+#[derive(Clone,Debug)]
+pub struct FovMap {
+    width: i32,
+    height: i32,
+    transparent: Vec<bool>,
+    in_fov: Vec<bool>,
+}
+
+impl FovMap {
+    /// A map the given size that nothing can be seen through until `set`.
+    pub fn new( width: i32, height: i32 ) -> Self {
+        let size = (width * height) as usize;
+        FovMap {
+            width,
+            height,
+            transparent: vec![false; size],
+            in_fov: vec![false; size],
+        }
+    }
+
+    pub fn set( &mut self, x: i32, y: i32, transparent: bool ) {
+        if let Some(index) = self.index(x, y) {
+            self.transparent[index] = transparent;
+        }
+    }
+
+    pub fn is_in_fov( &self, x: i32, y: i32 ) -> bool {
+        self.index(x, y).is_some_and(|index| self.in_fov[index])
+    }
+
+    /// Works out what can be seen from `(x,y)` out to `radius`, or the whole
+    /// map for a radius of 0. With `light_walls` the walls bounding what is
+    /// seen are visible too, not only the floor in front of them.
+    pub fn compute_fov( &mut self, x: i32, y: i32, radius: i32, light_walls: bool ) {
+        for seen in self.in_fov.iter_mut() {
+            *seen = false;
+        }
+        let origin = match self.index(x, y) {
+            Some(index) => index,
+            None => return,
+        };
+        self.in_fov[origin] = true;
+        let (xmin, xmax, ymin, ymax) = if radius > 0 {
+            (
+                (x - radius).max(0),
+                (x + radius).min(self.width - 1),
+                (y - radius).max(0),
+                (y + radius).min(self.height - 1),
+            )
+        } else {
+            (0, self.width - 1, 0, self.height - 1)
+        };
+        for edge_x in xmin..=xmax {
+            self.cast_ray((x,y), (edge_x,ymin), radius);
+            self.cast_ray((x,y), (edge_x,ymax), radius);
+        }
+        for edge_y in ymin..=ymax {
+            self.cast_ray((x,y), (xmin,edge_y), radius);
+            self.cast_ray((x,y), (xmax,edge_y), radius);
+        }
+        if light_walls {
+            self.light_walls((xmin,ymin), (x,y), (-1,-1));
+            self.light_walls((x,ymin), (xmax,y), (1,-1));
+            self.light_walls((xmin,y), (x,ymax), (-1,1));
+            self.light_walls((x,y), (xmax,ymax), (1,1));
+        }
+    }
+
+    fn index( &self, x: i32, y: i32 ) -> Option<usize> {
+        if (0..self.width).contains(&x) && (0..self.height).contains(&y) {
+            Some((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn cast_ray( &mut self, from: (i32,i32), to: (i32,i32), radius: i32 ) {
+        for (x,y) in line(from, to).into_iter().skip(1) {
+            let (dx, dy) = (x - from.0, y - from.1);
+            if radius > 0 && dx*dx + dy*dy > radius*radius {
+                return;
+            }
+            let index = match self.index(x, y) {
+                Some(index) => index,
+                None => return,
+            };
+            self.in_fov[index] = true;
+            if !self.transparent[index] {
+                return;
+            }
+        }
+    }
+
+    /// Rays stop on the first wall they reach, which leaves walls seen edge on
+    /// dark. Lights the walls next to visible floor on the far side from the
+    /// viewer, within one quadrant of the view.
+    fn light_walls( &mut self, (x0,y0): (i32,i32), (x1,y1): (i32,i32), (dx,dy): (i32,i32) ) {
+        let opaque = |map: &FovMap, x: i32, y: i32| -> Option<usize> {
+            let inside = (x0..=x1).contains(&x) && (y0..=y1).contains(&y);
+            map.index(x, y).filter(|&index| inside && !map.transparent[index])
+        };
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                let lit = self.index(x, y).is_some_and(|index| self.in_fov[index] && self.transparent[index]);
+                if !lit {
+                    continue;
+                }
+                for (wall_x, wall_y) in [(x+dx, y), (x, y+dy), (x+dx, y+dy)] {
+                    if let Some(wall) = opaque(self, wall_x, wall_y) {
+                        self.in_fov[wall] = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The tiles on a Bresenham line from `from` to `to`, both ends included.
+fn line( (x0,y0): (i32,i32), (x1,y1): (i32,i32) ) -> Vec<(i32,i32)> {
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let step_x = if x0 < x1 { 1 } else { -1 };
+    let step_y = if y0 < y1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    let (mut x, mut y) = (x0, y0);
+    let mut tiles = vec![(x,y)];
+    while (x,y) != (x1,y1) {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+        tiles.push((x,y));
+    }
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 9x5 room walled all round, with a pillar at (4,2)
+    fn room() -> FovMap {
+        let mut map = FovMap::new(9, 5);
+        for x in 1..8 {
+            for y in 1..4 {
+                map.set(x, y, (x,y) != (4,2));
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn walls_are_seen_but_not_through() {
+        let mut map = room();
+        map.compute_fov(3, 2, 0, true);
+        assert!(map.is_in_fov(3, 2));
+        assert!(map.is_in_fov(0, 0));
+        assert!(map.is_in_fov(4, 2));
+        assert!(!map.is_in_fov(6, 2), "hidden behind the pillar");
+        assert!(map.is_in_fov(5, 1));
+    }
+
+    #[test]
+    fn sight_ends_at_the_radius() {
+        let mut map = room();
+        map.compute_fov(1, 1, 2, true);
+        assert!(map.is_in_fov(3, 1));
+        assert!(!map.is_in_fov(4, 1));
+        assert!(!map.is_in_fov(-1, 0));
+    }
+}
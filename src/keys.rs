@@ -0,0 +1,206 @@
+//! What each key does in game, the same for every front end. The defaults
+//! can be changed from a JSON file, see `KeyBindings::load`.
+
+use crate::render::KeyEvent;
+use crate::render::KeyPress::{self, *};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+
+/// Everything a key can be bound to in game.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveUpLeft,
+    MoveUpRight,
+    MoveDownLeft,
+    MoveDownRight,
+    Wait,
+    PickUp,
+    Inventory,
+    Drop,
+    Descend,
+    MessageLog,
+    Fullscreen,
+    Exit,
+}
+
+impl Action {
+    /// The step a movement action takes.
+    pub fn direction(self) -> Option<(i32,i32)> {
+        use Action::*;
+        match self {
+            MoveUp => Some((0,-1)),
+            MoveDown => Some((0,1)),
+            MoveLeft => Some((-1,0)),
+            MoveRight => Some((1,0)),
+            MoveUpLeft => Some((-1,-1)),
+            MoveUpRight => Some((1,-1)),
+            MoveDownLeft => Some((-1,1)),
+            MoveDownRight => Some((1,1)),
+            _ => None,
+        }
+    }
+}
+
+// terminals mostly cannot tell the keypad from the number row, so both move
+const DEFAULT_BINDINGS: &[(Action, &[&str])] = &[
+    (Action::MoveUp, &["Up", "NumPad8", "8", "k"]),
+    (Action::MoveDown, &["Down", "NumPad2", "2", "j"]),
+    (Action::MoveLeft, &["Left", "NumPad4", "4", "h"]),
+    (Action::MoveRight, &["Right", "NumPad6", "6", "l"]),
+    (Action::MoveUpLeft, &["Home", "NumPad7", "7", "y"]),
+    (Action::MoveUpRight, &["PageUp", "NumPad9", "9", "u"]),
+    (Action::MoveDownLeft, &["End", "NumPad1", "1", "b"]),
+    (Action::MoveDownRight, &["PageDown", "NumPad3", "3", "n"]),
+    (Action::Wait, &["NumPad5", "5", "."]),
+    (Action::PickUp, &["g"]),
+    (Action::Inventory, &["i"]),
+    (Action::Drop, &["d"]),
+    (Action::Descend, &[">"]),
+    (Action::MessageLog, &["m"]),
+    (Action::Fullscreen, &["Alt+Enter"]),
+    (Action::Exit, &["Escape", "q"]),
+];
+
+const KEY_NAMES: &[(&str, KeyPress)] = &[
+    ("Escape", Escape), ("Backspace", Backspace), ("Tab", Tab), ("Enter", Enter),
+    ("Spacebar", Char(' ')), ("PageUp", PageUp), ("PageDown", PageDown),
+    ("End", End), ("Home", Home), ("Insert", Insert), ("Delete", Delete),
+    ("Up", Up), ("Down", Down), ("Left", Left), ("Right", Right),
+    ("NumPad0", NumPad(0)), ("NumPad1", NumPad(1)), ("NumPad2", NumPad(2)),
+    ("NumPad3", NumPad(3)), ("NumPad4", NumPad(4)), ("NumPad5", NumPad(5)),
+    ("NumPad6", NumPad(6)), ("NumPad7", NumPad(7)), ("NumPad8", NumPad(8)),
+    ("NumPad9", NumPad(9)), ("NumPadEnter", Enter),
+    ("F1", F(1)), ("F2", F(2)), ("F3", F(3)), ("F4", F(4)), ("F5", F(5)), ("F6", F(6)),
+    ("F7", F(7)), ("F8", F(8)), ("F9", F(9)), ("F10", F(10)), ("F11", F(11)), ("F12", F(12)),
+];
+
+/// Parses `Up`, `k`, `>` or `Alt+Enter` style names. A single character is
+/// the key that types it, so upper case letters mean with Shift.
+fn parse_key(spec: &str) -> Option<KeyEvent> {
+    let mut alt = false;
+    let mut ctrl = false;
+    let mut name = spec;
+    loop {
+        if let Some(rest) = name.strip_prefix("Alt+") {
+            alt = true;
+            name = rest;
+        } else if let Some(rest) = name.strip_prefix("Ctrl+") {
+            ctrl = true;
+            name = rest;
+        } else {
+            break;
+        }
+    }
+    let mut chars = name.chars();
+    let key = match (chars.next(), chars.next()) {
+        (Some(c), None) => Char(c),
+        _ => KEY_NAMES.iter().find(|(n, _)| *n == name)?.1,
+    };
+    Some(KeyEvent { key, alt, ctrl })
+}
+
+/// Which keys do what.
+#[derive(Clone,Debug)]
+pub struct KeyBindings {
+    bindings: Vec<(KeyEvent, Action)>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let mut bindings = vec![];
+        for &(action, specs) in DEFAULT_BINDINGS {
+            for spec in specs {
+                bindings.push((parse_key(spec).unwrap(), action));
+            }
+        }
+        KeyBindings { bindings }
+    }
+}
+
+impl KeyBindings {
+    /// Reads a JSON object mapping actions to lists of keys, e.g.
+    /// `{ "move_up": ["k", "Up"] }`. Actions left out keep their default keys,
+    /// except any the file gives to something else, and a missing file means
+    /// all defaults. A key listed under two actions is an error.
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let mut keys = KeyBindings::default();
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(keys),
+            Err(e) => return Err(e.into()),
+        };
+        keys.configure(&contents)?;
+        Ok(keys)
+    }
+
+    fn configure(&mut self, json: &str) -> Result<(), Box<dyn Error>> {
+        let config: BTreeMap<Action, Vec<String>> = serde_json::from_str(json)?;
+        let mut user: Vec<(KeyEvent, Action)> = vec![];
+        for (&action, specs) in &config {
+            for spec in specs {
+                let key = parse_key(spec)
+                    .ok_or_else(|| format!("unknown key \"{}\" for {:?}", spec, action))?;
+                if let Some(&(_, other)) = user.iter().find(|(bound, _)| *bound == key) {
+                    if other != action {
+                        return Err(format!("key \"{}\" is bound to both {:?} and {:?}", spec, other, action).into());
+                    }
+                }
+                user.push((key, action));
+            }
+        }
+        self.bindings.retain(|(key, action)| {
+            !config.contains_key(action) && !user.iter().any(|(bound, _)| bound == key)
+        });
+        user.append(&mut self.bindings);
+        self.bindings = user;
+        Ok(())
+    }
+
+    pub fn action(&self, key: KeyEvent) -> Option<Action> {
+        self.bindings.iter().find(|&&(bound, _)| bound == key).map(|&(_, action)| action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn letter(c: char) -> KeyEvent {
+        KeyPress::Char(c).into()
+    }
+
+    #[test]
+    fn user_keys_take_over_default_ones() {
+        let mut keys = KeyBindings::default();
+        keys.configure(r#"{ "move_right": ["d"], "drop": ["x"] }"#).unwrap();
+        assert_eq!(keys.action(letter('d')), Some(Action::MoveRight));
+        assert_eq!(keys.action(letter('x')), Some(Action::Drop));
+        assert_eq!(keys.action(letter('l')), None);
+        assert_eq!(keys.action(letter('g')), Some(Action::PickUp));
+    }
+
+    #[test]
+    fn a_key_under_two_actions_is_an_error() {
+        let mut keys = KeyBindings::default();
+        assert!(keys.configure(r#"{ "move_up": ["w"], "wait": ["w"] }"#).is_err());
+        assert!(keys.configure(r#"{ "move_up": ["Nowhere"] }"#).is_err());
+    }
+
+    #[test]
+    fn modifiers_and_names_are_part_of_the_key() {
+        let keys = KeyBindings::default();
+        let alt_enter = KeyEvent { key: Enter, alt: true, ctrl: false };
+        assert_eq!(keys.action(alt_enter), Some(Action::Fullscreen));
+        assert_eq!(keys.action(Enter.into()), None);
+        assert_eq!(keys.action(NumPad(8).into()), Some(Action::MoveUp));
+        assert_eq!(keys.action(letter('>')), Some(Action::Descend));
+    }
+}
@@ -0,0 +1,1535 @@
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+
+pub mod colors;
+pub mod ecs;
+pub mod fov;
+pub mod keys;
+pub mod mapgen;
+pub mod options;
+mod pathfinding;
+pub mod render;
+pub mod replay;
+pub mod status;
+pub mod templates;
+
+use colors::*;
+use ecs::{join, Entity, World};
+use fov::FovMap;
+use mapgen::{Generator, MapStats, Region};
+use status::{afflict, apply_status, remove_status, tick_statuses, Effect, Status};
+use templates::{from_dungeon_level, Templates, Transition};
+
+// map size
+pub const MAP_WIDTH: i32 = 80;
+pub const MAP_HEIGHT: i32 = 45;
+// the first entity spawned by `new_game`, and never despawned
+pub const PLAYER: Entity = Entity { index: 0, generation: 0 };
+// Field of view
+const FOV_LIGHT_WALLS: bool = true;
+const TORCH_RADIUS: i32 = 10;
+const BLIND_TORCH_RADIUS: i32 = 1;
+
+const HEAL_AMOUNT: i32 = 4;
+
+const LIGHTNING_DAMAGE: i32 = 40;
+const LIGHTNING_RANGE: i32 = 5;
+
+const CONFUSE_NUM_TURNS: i32 = 10;
+const CONFUSE_RANGE: i32 = 8;
+
+const FIREBALL_RADIUS: i32 = 3;
+const FIREBALL_DAMAGE: i32 = 12;
+const FIREBALL_RANGE: i32 = 8;
+
+const REGENERATE_NUM_TURNS: i32 = 20;
+const HASTE_NUM_TURNS: i32 = 15;
+
+const SLEEP_NUM_TURNS: i32 = 8;
+const SLEEP_RANGE: i32 = 8;
+
+const SLOW_NUM_TURNS: i32 = 15;
+const SLOW_RANGE: i32 = 8;
+
+const BLIND_NUM_TURNS: i32 = 10;
+const BLIND_RANGE: i32 = 8;
+
+const SAVE_FILE: &str = "savegame";
+
+// oldest messages are dropped beyond this many
+pub const MESSAGE_LIMIT: usize = 1000;
+
+// experience and level-ups
+const LEVEL_UP_BASE: i32 = 200;
+const LEVEL_UP_FACTOR: i32 = 150;
+
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+pub enum DeathCallback {
+    Player,
+    Monster,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+pub enum Item {
+    Heal,
+    Lightning,
+    Confuse,
+    Fireball,
+    Equip,
+    Regenerate,
+    Haste,
+    Sleep,
+    Slow,
+    Blind,
+}
+
+impl Item {
+    /// How far from the player the target of this item may be, `None` if it
+    /// needs no target.
+    pub fn target_range(self) -> Option<i32> {
+        use Item::*;
+        match self {
+            Lightning => Some(LIGHTNING_RANGE),
+            Confuse => Some(CONFUSE_RANGE),
+            Fireball => Some(FIREBALL_RANGE),
+            Sleep => Some(SLEEP_RANGE),
+            Slow => Some(SLOW_RANGE),
+            Blind => Some(BLIND_RANGE),
+            Heal | Equip | Regenerate | Haste => None,
+        }
+    }
+}
+
+pub enum UseResult {
+    UsedUp,
+    UsedAndKept,
+    Cancelled,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+pub enum Slot {
+    MainHand,
+    OffHand,
+    Body,
+    Head,
+    Ring,
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Slot::*;
+        match *self {
+            MainHand => write!(f, "main hand"),
+            OffHand => write!(f, "off hand"),
+            Body => write!(f, "body"),
+            Head => write!(f, "head"),
+            Ring => write!(f, "ring"),
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+pub struct Equipment {
+    pub slot: Slot,
+    pub equipped: bool,
+    pub max_hp_bonus: i32,
+    pub defense_bonus: i32,
+    pub power_bonus: i32,
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+pub struct Fighter {
+    pub base_max_hp: i32,
+    pub hp: i32,
+    pub base_defense: i32,
+    pub base_power: i32,
+    /// Experience gained so far for the player, what killing it is worth for a monster.
+    pub xp: i32,
+    /// Only goes up for the player, see `level_up`.
+    #[serde(default = "first_level")]
+    pub level: i32,
+    pub on_death: DeathCallback,
+    /// Put on whoever this fighter hurts and does not kill.
+    #[serde(default)]
+    pub on_hit: Option<Effect>,
+}
+
+fn first_level() -> i32 {
+    1
+}
+
+/// How a monster decides what to do, when its statuses leave it any choice.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub enum Ai {
+    Basic,
+}
+
+/// What every entity has: how it looks and whether it is in the way. The
+/// rest of it is made up of components on the `World`.
+#[derive(Debug,Serialize,Deserialize)]
+pub struct Object {
+    pub char: char,
+    pub color: Color,
+    pub name: String,
+    pub blocks: bool,
+    pub alive: bool,
+    pub always_visible: bool,
+}
+
+impl Object {
+    pub fn new( char: char, name: &str, color: Color, blocks: bool ) -> Self {
+        Object{
+            char,
+            color,
+            name: name.into(),
+            blocks,
+            alive: false,
+            always_visible: false,
+        }
+    }
+}
+
+fn distance( (x1,y1): (i32,i32), (x2,y2): (i32,i32) ) -> f32 {
+    (((x2 - x1).pow(2) + (y2 - y1).pow(2)) as f32).sqrt()
+}
+
+/// Returns the experience the target was worth if the damage killed it.
+fn take_damage( id: Entity, damage: i32, game: &mut Game, world: &mut World ) -> Option<i32> {
+    // the player's body keeps its fighter, but cannot die twice
+    if !world.objects.get(id).is_some_and(|o| o.alive) {
+        return None;
+    }
+    let fighter = world.fighters.get_mut(id)?;
+    if damage > 0 {
+        fighter.hp -= damage;
+    }
+    let fighter = *fighter;
+    if fighter.hp <= 0 {
+        world.objects[id].alive = false;
+        fighter.on_death.callback(id, game, world);
+        return Some(fighter.xp);
+    }
+    if damage > 0 && world.has_status(id, Status::Sleep) {
+        remove_status(id, Status::Sleep, world);
+        let text = if id == PLAYER {
+            "You wake up!".to_string()
+        } else {
+            format!("The {} wakes up!", world.objects[id].name)
+        };
+        game.messages.add(text, LIGHT_GREY);
+    }
+    None
+}
+
+fn gain_xp( id: Entity, xp: i32, world: &mut World ) {
+    if let Some(fighter) = world.fighters.get_mut(id) {
+        fighter.xp += xp;
+    }
+}
+
+fn heal( id: Entity, amount: i32, game: &Game, world: &mut World ) {
+    if !world.objects.get(id).is_some_and(|o| o.alive) {
+        return;
+    }
+    if let Some(fighter) = world.fighters.get_mut(id) {
+        fighter.hp += amount;
+    }
+    clamp_hp(id, game, world);
+}
+
+/// Brings hp back down to max hp, which drops when a max HP bonus comes off.
+fn clamp_hp( id: Entity, game: &Game, world: &mut World ) {
+    let max_hp = world.max_hp(id, game);
+    if let Some(fighter) = world.fighters.get_mut(id) {
+        fighter.hp = fighter.hp.min(max_hp);
+    }
+}
+
+fn attack( attacker: Entity, target: Entity, game: &mut Game, world: &mut World ) {
+    let damage = world.power(attacker, game) - world.defense(target, game);
+    if damage > 0 {
+        game.messages.add(
+            format!( "{} attacks {} for {}.",
+            world.objects[attacker].name, world.objects[target].name, damage
+            ), WHITE );
+        if let Some(xp) = take_damage(target, damage, game, world) {
+            gain_xp(attacker, xp, world);
+        } else if let Some(effect) = world.fighters.get(attacker).and_then(|f| f.on_hit) {
+            afflict(target, effect, game, world);
+        }
+    } else {
+        game.messages.add(
+            format!(
+            "{} attacks {} but has no effect.",
+            world.objects[attacker].name, world.objects[target].name
+            ), WHITE );
+    }
+}
+
+impl World {
+    /// Equipment currently worn, only the player carries an inventory to wear it from.
+    pub fn get_all_equipped( &self, id: Entity, game: &Game ) -> Vec<Equipment> {
+        if id == PLAYER {
+            game.inventory
+                .iter()
+                .filter_map(|&item| self.equipment.get(item).copied())
+                .filter(|equipment| equipment.equipped)
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    pub fn power( &self, id: Entity, game: &Game ) -> i32 {
+        let base_power = self.fighters.get(id).map_or(0, |f| f.base_power);
+        let bonus: i32 = self.get_all_equipped(id, game).iter().map(|e| e.power_bonus).sum();
+        base_power + bonus
+    }
+
+    pub fn defense( &self, id: Entity, game: &Game ) -> i32 {
+        let base_defense = self.fighters.get(id).map_or(0, |f| f.base_defense);
+        let bonus: i32 = self.get_all_equipped(id, game).iter().map(|e| e.defense_bonus).sum();
+        base_defense + bonus
+    }
+
+    pub fn max_hp( &self, id: Entity, game: &Game ) -> i32 {
+        let base_max_hp = self.fighters.get(id).map_or(0, |f| f.base_max_hp);
+        let bonus: i32 = self.get_all_equipped(id, game).iter().map(|e| e.max_hp_bonus).sum();
+        base_max_hp + bonus
+    }
+}
+
+fn equip( id: Entity, world: &mut World, messages: &mut Messages ) {
+    if let Some(equipment) = world.equipment.get_mut(id) {
+        if !equipment.equipped {
+            equipment.equipped = true;
+            messages.add(
+                format!("Equipped {} on {}.", world.objects[id].name, equipment.slot),
+                LIGHT_GREEN,
+            );
+        }
+    } else {
+        messages.add(
+            format!("Can't equip {:?} because it's not an Equipment.", world.objects[id].name),
+            RED,
+        );
+    }
+}
+
+fn dequip( id: Entity, world: &mut World, messages: &mut Messages ) {
+    if let Some(equipment) = world.equipment.get_mut(id) {
+        if equipment.equipped {
+            equipment.equipped = false;
+            messages.add(
+                format!("Dequipped {} from {}.", world.objects[id].name, equipment.slot),
+                LIGHT_YELLOW,
+            );
+        }
+    } else {
+        messages.add(
+            format!("Can't dequip {:?} because it's not an Equipment.", world.objects[id].name),
+            RED,
+        );
+    }
+}
+
+impl DeathCallback {
+    fn callback( self, id: Entity, game: &mut Game, world: &mut World ){
+        use DeathCallback::*;
+        let callback = match self {
+            Player => player_death,
+            Monster => monster_death,
+        };
+        callback(id,game,world);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Tile {
+    pub blocked: bool,
+    pub block_sight: bool,
+    pub explored: bool,
+}
+
+impl Tile {
+    pub fn empty() -> Self {
+        Tile {
+            blocked: false,
+            block_sight: false,
+            explored: false,
+        }
+    }
+    pub fn wall() -> Self {
+        Tile {
+            blocked: true,
+            block_sight: true,
+            explored: false,
+        }
+    }
+}
+
+pub type Map = Vec<Vec<Tile>>;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Rect {
+    x1: i32,
+    y1: i32,
+    x2: i32,
+    y2: i32,
+}
+
+impl Rect {
+    pub fn new( x: i32, y: i32, w: i32, h: i32 ) -> Self {
+        Rect {
+            x1: x,
+            y1: y,
+            x2: x+w,
+            y2: y+h,
+        }
+    }
+    pub fn center(&self) -> (i32,i32) {
+        let center_x = (self.x1+self.x2)/2;
+        let center_y = (self.y1+self.y2)/2;
+        (center_x,center_y)
+    }
+    pub fn intersects_with( &self, other: &Rect ) -> bool {
+        (self.x1 <= other.x2)
+        && (self.x2 >= other.x1)
+        && (self.y1 <= other.y2)
+        && (self.y2 >= other.y1)
+    }
+}
+
+fn is_blocked( x: i32, y: i32, map: &Map, world: &World ) -> bool {
+    if map[x as usize][y as usize].blocked {
+        return true;
+    }
+    world.positions.at((x,y)).iter().any(
+        |&id| world.objects[id].blocks )
+}
+
+fn move_by( id: Entity, dx: i32, dy: i32, map: &Map, world: &mut World ) {
+    let (x,y) = world.positions[id];
+    if !is_blocked(x+dx, y+dy, map, world) {
+        world.positions.insert(id, (x+dx, y+dy));
+    }
+}
+
+fn choose_template<'a, T>(
+    templates: &'a [T],
+    chance: impl Fn(&T) -> &[Transition],
+    level: u32,
+    rng: &mut Pcg32,
+) -> Option<&'a T> {
+    let weights = templates.iter().map(|template| from_dungeon_level(chance(template), level));
+    // nothing to pick when every weight is zero at this depth
+    let choice = WeightedIndex::new(weights).ok()?;
+    Some(&templates[choice.sample(rng)])
+}
+
+fn place_objects( region: &Region, level: u32, templates: &Templates, map: &Map, world: &mut World, rng: &mut Pcg32 ){
+    let max_monsters = from_dungeon_level(&templates.max_room_monsters, level);
+    let num_monsters = rng.gen_range(0, max_monsters+1);
+    for _ in 0..num_monsters {
+        let (x,y) = region.random_tile(rng);
+        if !is_blocked(x, y, map, world) {
+            if let Some(monster) = choose_template(&templates.monsters, |monster| &monster.chance, level, rng) {
+                monster.spawn(world, x, y);
+            }
+        }
+    }
+
+    let max_items = from_dungeon_level(&templates.max_room_items, level);
+    let num_items = rng.gen_range(0, max_items+1);
+    for _ in 0..num_items {
+        let (x,y) = region.random_tile(rng);
+        if !is_blocked(x, y, map, world) {
+            if let Some(item) = choose_template(&templates.items, |item| &item.chance, level, rng) {
+                item.spawn(world, x, y);
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Messages {
+    messages: VecDeque<(String,Color)>,
+    #[serde(default = "default_message_limit")]
+    limit: usize,
+}
+
+fn default_message_limit() -> usize {
+    MESSAGE_LIMIT
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Messages {
+    pub fn new() -> Self {
+        Self::with_limit(MESSAGE_LIMIT)
+    }
+    /// Keeps at most `limit` messages, at least one is always kept.
+    pub fn with_limit( limit: usize ) -> Self {
+        Self { messages: VecDeque::new(), limit: cmp::max(limit, 1) }
+    }
+    pub fn set_limit( &mut self, limit: usize ) {
+        self.limit = cmp::max(limit, 1);
+        while self.messages.len() > self.limit {
+            self.messages.pop_front();
+        }
+    }
+    pub fn add<T: Into<String>>( &mut self, messages: T, color: Color ){
+        if self.messages.len() >= self.limit {
+            self.messages.pop_front();
+        }
+        self.messages.push_back((messages.into(),color));
+    }
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &(String,Color)> + ExactSizeIterator {
+        self.messages.iter()
+    }
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+    /// Index of the nearest message containing `query`, ignoring case, looking
+    /// from `start` towards older messages if `backwards` and newer ones otherwise.
+    pub fn find( &self, query: &str, start: usize, backwards: bool ) -> Option<usize> {
+        let query = query.to_lowercase();
+        let matches = |index: &usize| self.messages[*index].0.to_lowercase().contains(&query);
+        if backwards {
+            (0..cmp::min(start+1, self.messages.len())).rev().find(matches)
+        } else {
+            (start..self.messages.len()).find(matches)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Game {
+    pub map: Map,
+    // how generating the current level went
+    #[serde(default)]
+    pub map_stats: MapStats,
+    pub messages: Messages,
+    // carried items are entities without a position
+    pub inventory: Vec<Entity>,
+    pub dungeon_level: u32,
+    // turns the player has taken, paces creatures faster or slower than them
+    #[serde(default)]
+    pub turn: u64,
+    // everything random in the dungeon is drawn from here, so a seed replays a run
+    pub seed: u64,
+    pub rng: Pcg32,
+    // picked at the start of a run, every level is laid out the same way
+    #[serde(default)]
+    pub generator: Generator,
+    // what new levels are stocked with, kept with the save so a run stays the same
+    #[serde(default)]
+    pub templates: Templates,
+    // rebuilt from the map by `initialise_fov` after a load
+    #[serde(skip, default = "new_fov_map")]
+    pub fov: FovMap,
+    // what the player would see without blindness, monsters standing in it
+    // see the player
+    #[serde(skip, default = "new_fov_map")]
+    pub sight: FovMap,
+}
+
+fn new_fov_map() -> FovMap {
+    FovMap::new(MAP_WIDTH, MAP_HEIGHT)
+}
+
+fn make_map(
+    world: &mut World,
+    level: u32,
+    generator: Generator,
+    templates: &Templates,
+    rng: &mut Pcg32,
+) -> (Map, MapStats) {
+    // only the player and what they carry survive the trip to a new level
+    let left_behind: Vec<Entity> = world.positions.entities().filter(|&id| id != PLAYER).collect();
+    for id in left_behind {
+        world.despawn(id);
+    }
+    let (map, regions, stats) = generator.generate(rng);
+    // the player starts in the first region
+    world.positions.insert(PLAYER, regions[0].center());
+    for region in &regions {
+        place_objects(region, level, templates, &map, world, rng);
+    }
+    // stairs down sit in the center of the last region
+    let mut stairs = Object::new('>', "stairs", WHITE, false);
+    stairs.always_visible = true;
+    let stairs = world.spawn(stairs);
+    world.positions.insert(stairs, regions[regions.len()-1].center());
+    world.stairs.insert(stairs, ());
+    (map, stats)
+}
+
+/// Mentions it in the log when a level needed fixing up to join together.
+fn report_map_repairs( game: &mut Game ) {
+    if game.map_stats.repaired() {
+        let text = format!("Level {} layout: {}.", game.dungeon_level, game.map_stats);
+        game.messages.add(text, DARK_GREY);
+    }
+}
+
+fn next_level( game: &mut Game, world: &mut World ) {
+    game.messages.add(
+        "You take a moment to rest, and recover your strength.",
+        VIOLET,
+    );
+    let heal_hp = world.max_hp(PLAYER, game)/2;
+    heal(PLAYER, heal_hp, game, world);
+    game.messages.add(
+        "After a rare moment of peace, you descend deeper into the heart of the dungeon...",
+        RED,
+    );
+    game.dungeon_level += 1;
+    let (map, map_stats) = make_map(world, game.dungeon_level, game.generator, &game.templates, &mut game.rng);
+    game.map = map;
+    game.map_stats = map_stats;
+    report_map_repairs(game);
+    initialise_fov(game);
+}
+
+fn move_towards( id: Entity, target_x: i32, target_y: i32, map: &Map, world: &mut World ) {
+    let (x,y) = world.positions[id];
+    let dx = target_x - x;
+    let dy = target_y - y;
+    let distance = ((dx.pow(2)+dy.pow(2)) as f32).sqrt();
+    let dx = (dx as f32 / distance ).round() as i32;
+    let dy = (dy as f32 / distance ).round() as i32;
+    move_by(id, dx, dy, map, world);
+}
+
+/// Follows a path around walls and other monsters when there is one, and
+/// heads straight for the target otherwise.
+fn move_astar( id: Entity, target_x: i32, target_y: i32, map: &Map, world: &mut World ) {
+    match pathfinding::first_step(world.positions[id], (target_x, target_y), map, world) {
+        Some((dx, dy)) => move_by(id, dx, dy, map, world),
+        None => move_towards(id, target_x, target_y, map, world),
+    }
+}
+
+fn player_move_or_attack( dx: i32, dy: i32, game: &mut Game, world: &mut World ) {
+    let (x,y) = world.positions[PLAYER];
+    let target_id = world.positions.at((x+dx,y+dy)).iter().copied()
+        .find(|&id| id != PLAYER && world.fighters.contains(id));
+    match target_id {
+        Some(target_id) => {
+            attack(PLAYER, target_id, game, world);
+        }
+        None => {
+            move_by(PLAYER, dx, dy, &game.map, world);
+        }
+    }
+}
+
+pub fn closest_monster( game: &Game, world: &World, max_range: i32 ) -> Option<Entity> {
+    let mut closest_enemy = None;
+    let mut closest_dist = (max_range+1) as f32;
+    for (id, _, &(x,y)) in join(&world.ais, &world.positions) {
+        if (id != PLAYER)
+            && world.fighters.contains(id)
+            && game.fov.is_in_fov(x,y)
+            {
+                let dist = distance(world.positions[PLAYER], (x,y));
+                if dist < closest_dist {
+                    closest_enemy = Some(id);
+                    closest_dist = dist;
+                }
+            }
+    }
+    closest_enemy
+}
+
+/// A target must be visible to the player and no further away than `max_range`.
+pub fn is_valid_target( game: &Game, world: &World, (x,y): (i32,i32), max_range: i32 ) -> bool {
+    (0..MAP_WIDTH).contains(&x) && (0..MAP_HEIGHT).contains(&y)
+        && game.fov.is_in_fov(x, y)
+        && distance(world.positions[PLAYER], (x,y)) <= max_range as f32
+}
+
+/// The monster standing on a chosen target tile, if the target is valid.
+fn targeted_monster(
+    target: Option<(i32,i32)>,
+    max_range: i32,
+    game: &mut Game,
+    world: &World,
+) -> Option<Entity> {
+    let target = target?;
+    if !is_valid_target(game, world, target, max_range) {
+        game.messages.add("That is out of range.",RED);
+        return None;
+    }
+    let monster_id = world.positions.at(target).iter().copied().find(|&id| {
+        id != PLAYER && world.fighters.contains(id) && world.ais.contains(id)
+    });
+    if monster_id.is_none() {
+        game.messages.add("There is no enemy there.",RED);
+    }
+    monster_id
+}
+
+fn pick_item_up( object_id: Entity, game: &mut Game, world: &mut World ){
+    if game.inventory.len() >= 26 {
+        game.messages.add(format!(
+        "Inventory full, cannot pickup {}.", world.objects[object_id].name
+        ), RED);
+    } else {
+        world.positions.remove(object_id);
+        game.messages.add(format!(
+        "You picked up a {}.", world.objects[object_id].name
+        ), GREEN);
+        game.inventory.push(object_id);
+    }
+}
+
+fn drop_item( inventory_id: usize, game: &mut Game, world: &mut World ) {
+    let item = game.inventory.remove(inventory_id);
+    if world.equipment.get(item).is_some_and(|e| e.equipped) {
+        dequip(item, world, &mut game.messages);
+        clamp_hp(PLAYER, game, world);
+    }
+    world.positions.insert(item, world.positions[PLAYER]);
+    game.messages.add(format!("You dropped a {}.", world.objects[item].name), YELLOW);
+}
+
+fn cast_heal(
+    _inventory_id: usize,
+    _target: Option<(i32,i32)>,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    if let Some(fighter) = world.fighters.get(PLAYER) {
+        if fighter.hp == world.max_hp(PLAYER, game) {
+            game.messages.add("You are already at full health.",RED);
+        }
+        heal(PLAYER, HEAL_AMOUNT, game, world);
+        game.messages.add("Your wounds start to heal!", LIGHT_VIOLET);
+        return UseResult::UsedUp;
+    }
+    UseResult::Cancelled
+}
+
+fn cast_lightning(
+    _inventory_id: usize,
+    target: Option<(i32,i32)>,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    let monster_id = targeted_monster( target, LIGHTNING_RANGE, game, world );
+    if let Some(monster_id) = monster_id {
+        game.messages.add(
+            format!("A lightning bolt strikes the {} for {} damage.",world.objects[monster_id].name,LIGHTNING_DAMAGE),
+            LIGHTER_BLUE
+            );
+        if let Some(xp) = take_damage(monster_id, LIGHTNING_DAMAGE, game, world) {
+            gain_xp(PLAYER, xp, world);
+        }
+        UseResult::UsedUp
+    } else {
+        UseResult::Cancelled
+    }
+}
+
+fn cast_confuse(
+    _inventory_id: usize,
+    target: Option<(i32,i32)>,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    let monster_id = targeted_monster( target, CONFUSE_RANGE, game, world );
+    if let Some(monster_id) = monster_id {
+        apply_status(monster_id, Effect { status: Status::Confusion, turns: CONFUSE_NUM_TURNS }, world);
+        game.messages.add(
+            format!("The {} begins to stumble around!", world.objects[monster_id].name), LIGHT_GREEN,
+        );
+        UseResult::UsedUp
+    } else {
+        UseResult::Cancelled
+    }
+}
+
+fn cast_fireball(
+    _inventory_id: usize,
+    target: Option<(i32,i32)>,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    let (x, y) = match target {
+        Some(target) if is_valid_target(game, world, target, FIREBALL_RANGE) => target,
+        Some(_) => {
+            game.messages.add("That is out of range.",RED);
+            return UseResult::Cancelled;
+        }
+        None => return UseResult::Cancelled,
+    };
+    game.messages.add(
+        format!("The fireball explodes, burning everything within {} tiles!", FIREBALL_RADIUS),
+        ORANGE,
+    );
+    // the player is not spared, but gets no experience for burning themselves
+    let mut xp_to_gain = 0;
+    let burned: Vec<Entity> = join(&world.fighters, &world.positions)
+        .filter(|&(_, _, &pos)| distance(pos, (x,y)) <= FIREBALL_RADIUS as f32)
+        .map(|(id, _, _)| id)
+        .collect();
+    for id in burned {
+        game.messages.add(
+            format!("The {} gets burned for {} hit points.", world.objects[id].name, FIREBALL_DAMAGE),
+            ORANGE,
+        );
+        if let Some(xp) = take_damage(id, FIREBALL_DAMAGE, game, world) {
+            if id != PLAYER {
+                xp_to_gain += xp;
+            }
+        }
+    }
+    gain_xp(PLAYER, xp_to_gain, world);
+    UseResult::UsedUp
+}
+
+fn cast_regenerate(
+    _inventory_id: usize,
+    _target: Option<(i32,i32)>,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    apply_status(PLAYER, Effect { status: Status::Regeneration, turns: REGENERATE_NUM_TURNS }, world);
+    game.messages.add("Your wounds begin to close by themselves.", LIGHT_VIOLET);
+    UseResult::UsedUp
+}
+
+fn cast_haste(
+    _inventory_id: usize,
+    _target: Option<(i32,i32)>,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    apply_status(PLAYER, Effect { status: Status::Haste, turns: HASTE_NUM_TURNS }, world);
+    game.messages.add("The world around you slows to a crawl.", LIGHT_YELLOW);
+    UseResult::UsedUp
+}
+
+/// Puts `status` on the monster at the chosen target.
+fn cast_status(
+    target: Option<(i32,i32)>,
+    max_range: i32,
+    effect: Effect,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    match targeted_monster( target, max_range, game, world ) {
+        Some(monster_id) => {
+            afflict(monster_id, effect, game, world);
+            UseResult::UsedUp
+        }
+        None => UseResult::Cancelled,
+    }
+}
+
+fn cast_sleep(
+    _inventory_id: usize,
+    target: Option<(i32,i32)>,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    cast_status(target, SLEEP_RANGE, Effect { status: Status::Sleep, turns: SLEEP_NUM_TURNS }, game, world)
+}
+
+fn cast_slow(
+    _inventory_id: usize,
+    target: Option<(i32,i32)>,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    cast_status(target, SLOW_RANGE, Effect { status: Status::Slow, turns: SLOW_NUM_TURNS }, game, world)
+}
+
+fn cast_blind(
+    _inventory_id: usize,
+    target: Option<(i32,i32)>,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    cast_status(target, BLIND_RANGE, Effect { status: Status::Blindness, turns: BLIND_NUM_TURNS }, game, world)
+}
+
+fn get_equipped_in_slot( slot: Slot, inventory: &[Entity], world: &World ) -> Option<Entity> {
+    inventory.iter().copied().find(|&item| {
+        world.equipment.get(item).is_some_and(|e| e.equipped && e.slot == slot)
+    })
+}
+
+fn toggle_equipment(
+    inventory_id: usize,
+    _target: Option<(i32,i32)>,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    let item = game.inventory[inventory_id];
+    let equipment = match world.equipment.get(item) {
+        Some(&equipment) => equipment,
+        None => return UseResult::Cancelled,
+    };
+    if equipment.equipped {
+        dequip(item, world, &mut game.messages);
+    } else {
+        // whatever already occupies the slot comes off first
+        if let Some(current) = get_equipped_in_slot(equipment.slot, &game.inventory, world) {
+            dequip(current, world, &mut game.messages);
+        }
+        equip(item, world, &mut game.messages);
+    }
+    clamp_hp(PLAYER, game, world);
+    UseResult::UsedAndKept
+}
+
+fn player_death( id: Entity, game: &mut Game, world: &mut World ) {
+    game.messages.add("You dead!",RED);
+    let player = &mut world.objects[id];
+    player.char='%';
+    player.color=DARK_RED;
+}
+
+fn monster_death( id: Entity, game: &mut Game, world: &mut World ) {
+    let xp = world.fighters.remove(id).map_or(0, |f| f.xp);
+    world.ais.remove(id);
+    world.statuses.remove(id);
+    let monster = &mut world.objects[id];
+    game.messages.add(
+        format!("{} dies! You gain {} experience points.",
+            monster.name, xp
+        ), ORANGE);
+    monster.char='%';
+    monster.color=DARK_RED;
+    monster.blocks=false;
+    monster.name=format!("remains of {}", monster.name);
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum PlayerAction {
+    TookTurn,
+    DidntTakeTurn,
+    Exit,
+}
+
+fn ai_basic( monster_id: Entity, game: &mut Game, world: &mut World ) -> Ai {
+    let (monster_x, monster_y) = world.positions[monster_id];
+    let (player_x,player_y) = world.positions[PLAYER];
+    let next_to_player = distance((monster_x, monster_y), (player_x, player_y)) < 2.0;
+    // a blind monster only notices the player close enough to touch
+    let sees_player = if world.has_status(monster_id, Status::Blindness) {
+        next_to_player
+    } else {
+        game.sight.is_in_fov(monster_x, monster_y)
+    };
+    if sees_player {
+        if !next_to_player {
+            move_astar(monster_id, player_x, player_y, &game.map, world);
+        } else if world.fighters.get(PLAYER).is_some_and(|f| f.hp > 0 ) {
+            attack(monster_id, PLAYER, game, world);
+        }
+    }
+    Ai::Basic
+}
+
+fn ai_confused( monster_id: Entity, game: &mut Game, world: &mut World ) {
+    move_by(
+        monster_id,
+        game.rng.gen_range(-1, 2),
+        game.rng.gen_range(-1, 2),
+        &game.map,
+        world
+    );
+}
+
+fn ai_take_turn( monster_id: Entity, game: &mut Game, world: &mut World ) {
+    use Ai::*;
+    if world.has_status(monster_id, Status::Sleep) {
+        return;
+    }
+    if world.has_status(monster_id, Status::Confusion) {
+        ai_confused(monster_id, game, world);
+        return;
+    }
+    if let Some(ai) = world.ais.remove(monster_id) {
+        let new_ai = match ai {
+            Basic => ai_basic(monster_id, game, world),
+        };
+        world.ais.insert(monster_id, new_ai);
+    }
+}
+
+pub fn level_up_xp( level: i32 ) -> i32 {
+    LEVEL_UP_BASE + level * LEVEL_UP_FACTOR
+}
+
+pub fn level_up_pending( world: &World ) -> bool {
+    world.fighters.get(PLAYER).is_some_and(|f| f.xp >= level_up_xp(f.level))
+}
+
+pub fn level_up( stat: Stat, game: &mut Game, world: &mut World ) {
+    let fighter = match world.fighters.get_mut(PLAYER) {
+        Some(fighter) if fighter.xp >= level_up_xp(fighter.level) => fighter,
+        _ => return,
+    };
+    match stat {
+        Stat::Constitution => {
+            fighter.base_max_hp += 20;
+            fighter.hp += 20;
+        }
+        Stat::Strength => {
+            fighter.base_power += 1;
+        }
+        Stat::Agility => {
+            fighter.base_defense += 1;
+        }
+    }
+    fighter.xp -= level_up_xp(fighter.level);
+    fighter.level += 1;
+    game.messages.add(
+        format!("Your battle skills grow stronger! You reached level {}!", fighter.level),
+        YELLOW,
+    );
+}
+
+fn use_item(
+    inventory_id: usize,
+    target: Option<(i32,i32)>,
+    game: &mut Game,
+    world: &mut World,
+) -> UseResult {
+    use Item::*;
+    if let Some(&item) = world.items.get(game.inventory[inventory_id]) {
+        let on_use = match item {
+            Heal => cast_heal,
+            Lightning => cast_lightning,
+            Confuse => cast_confuse,
+            Fireball => cast_fireball,
+            Equip => toggle_equipment,
+            Regenerate => cast_regenerate,
+            Haste => cast_haste,
+            Sleep => cast_sleep,
+            Slow => cast_slow,
+            Blind => cast_blind,
+        };
+        let result = on_use( inventory_id, target, game, world );
+        match result {
+            UseResult::UsedUp => {
+                let item = game.inventory.remove(inventory_id);
+                world.despawn(item);
+            }
+            UseResult::UsedAndKept => {}
+            UseResult::Cancelled => {
+                game.messages.add("Cancelled",WHITE);
+            }
+        }
+        result
+    } else {
+        game.messages.add(
+            format!("The {} cannot be used.", world.objects[game.inventory[inventory_id]].name),
+            WHITE);
+        UseResult::Cancelled
+    }
+}
+
+pub fn save_game( game: &Game, world: &World ) -> Result<(), Box<dyn Error>> {
+    let save_data = serde_json::to_string(&(game, world))?;
+    let mut file = File::create(SAVE_FILE)?;
+    file.write_all(save_data.as_bytes())?;
+    Ok(())
+}
+
+/// Dead runs are not continued, so their save goes away.
+pub fn delete_save_game() -> std::io::Result<()> {
+    std::fs::remove_file(SAVE_FILE)
+}
+
+pub fn load_game() -> Result<(Game, World), Box<dyn Error>> {
+    let mut json_save_state = String::new();
+    let mut file = File::open(SAVE_FILE)?;
+    file.read_to_string(&mut json_save_state)?;
+    game_from_save(&json_save_state)
+}
+
+/// The game `save_game` wrote as `json`, ready to carry on playing.
+fn game_from_save( json: &str ) -> Result<(Game, World), Box<dyn Error>> {
+    let (mut game, world) = serde_json::from_str::<(Game, World)>(json)?;
+    initialise_fov(&mut game);
+    update_fov(&mut game, &world);
+    Ok((game, world))
+}
+
+pub fn new_game( seed: u64, generator: Generator, templates: Templates ) -> (Game, World) {
+    let mut rng = Pcg32::seed_from_u64(seed);
+    let mut world = World::new();
+    let mut player = Object::new( '@', "player", WHITE, true );
+    player.alive = true;
+    let player = world.spawn(player);
+    debug_assert_eq!(player, PLAYER);
+    world.fighters.insert( player, Fighter {
+        base_max_hp: 30,
+        hp: 30,
+        base_defense: 2,
+        base_power: 3,
+        xp: 0,
+        level: 1,
+        on_death: DeathCallback::Player,
+        on_hit: None,
+    });
+    // start out with a dagger in hand
+    let dagger = world.spawn(Object::new('-', "dagger", SKY, false));
+    world.items.insert(dagger, Item::Equip);
+    world.equipment.insert(dagger, Equipment {
+        slot: Slot::MainHand,
+        equipped: true,
+        max_hp_bonus: 0,
+        defense_bonus: 0,
+        power_bonus: 2,
+    });
+    let (map, map_stats) = make_map( &mut world, 1, generator, &templates, &mut rng );
+    let mut game = Game {
+        map,
+        map_stats,
+        messages: Messages::new(),
+        inventory: vec![ dagger ],
+        dungeon_level: 1,
+        turn: 0,
+        seed,
+        rng,
+        generator,
+        templates,
+        fov: new_fov_map(),
+        sight: new_fov_map(),
+    };
+    initialise_fov(&mut game);
+    update_fov(&mut game, &world);
+    game.messages.add(
+        "Welcome, stranger! Prepare to perish in the Tombs of the Ancient Kings.",
+        RED,
+    );
+    game.messages.add(format!("Dungeon seed: {}", seed), DARK_GREY);
+    report_map_repairs(&mut game);
+    (game, world)
+}
+
+fn initialise_fov( game: &mut Game ) {
+    for y in 0..MAP_HEIGHT {
+        for x in 0..MAP_WIDTH {
+            game.fov.set(x, y, !game.map[x as usize][y as usize].block_sight);
+        }
+    }
+}
+
+/// Recomputes what the player sees and marks it explored.
+fn update_fov( game: &mut Game, world: &World ) {
+    let (x,y) = world.positions[PLAYER];
+    game.fov.compute_fov(x,y, TORCH_RADIUS, FOV_LIGHT_WALLS);
+    game.sight.clone_from(&game.fov);
+    if world.has_status(PLAYER, Status::Blindness) {
+        game.fov.compute_fov(x,y, BLIND_TORCH_RADIUS, FOV_LIGHT_WALLS);
+    }
+    for y in 0..MAP_HEIGHT {
+        for x in 0..MAP_WIDTH {
+            if game.fov.is_in_fov(x, y) {
+                game.map[x as usize][y as usize].explored = true;
+            }
+        }
+    }
+}
+
+/// Everything the player can ask of the simulation on their turn.
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+pub enum Command {
+    Move { dx: i32, dy: i32 },
+    Wait,
+    PickUp,
+    /// `target` is the tile picked for items with a `target_range`, `None`
+    /// when targeting was cancelled.
+    UseItem { index: usize, target: Option<(i32,i32)> },
+    Drop(usize),
+    Descend,
+    Exit,
+}
+
+/// Stats a level-up can raise.
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+pub enum Stat {
+    Constitution,
+    Strength,
+    Agility,
+}
+
+/// What a front end supplies to drive `play_game`: it shows the state however it
+/// likes and answers with the player's choices.
+pub trait Frontend {
+    /// Blocks until the player has decided on the next command.
+    fn next_command( &mut self, game: &Game, world: &World ) -> Command;
+    /// Asked while a level-up is pending, `None` leaves it pending.
+    fn choose_level_up( &mut self, game: &Game, world: &World ) -> Option<Stat>;
+}
+
+fn player_turn( command: Command, game: &mut Game, world: &mut World ) -> PlayerAction {
+    use PlayerAction::*;
+    let player_alive = world.objects[PLAYER].alive;
+    if player_alive && command != Command::Exit && world.has_status(PLAYER, Status::Sleep) {
+        game.messages.add("You are fast asleep.", LIGHT_GREY);
+        return TookTurn;
+    }
+    match ( command, player_alive ) {
+        ( Command::Exit, _ ) => Exit,
+        ( Command::Move { dx, dy }, true ) => {
+            // confusion sends the player off in any direction
+            let (dx, dy) = if world.has_status(PLAYER, Status::Confusion) {
+                (game.rng.gen_range(-1, 2), game.rng.gen_range(-1, 2))
+            } else {
+                (dx, dy)
+            };
+            player_move_or_attack(dx,dy,game,world);
+            TookTurn
+        }
+        ( Command::Wait, true ) => TookTurn,
+        ( Command::PickUp, true ) => {
+            let item_id = world.positions.at(world.positions[PLAYER]).iter().copied()
+                .find(|&id| world.items.contains(id));
+            if let Some(item_id) = item_id {
+                pick_item_up(item_id, game, world);
+            }
+            DidntTakeTurn
+        }
+        ( Command::UseItem { index, target }, true ) => {
+            if index >= game.inventory.len() {
+                return DidntTakeTurn;
+            }
+            match use_item(index, target, game, world) {
+                UseResult::Cancelled => DidntTakeTurn,
+                UseResult::UsedUp | UseResult::UsedAndKept => TookTurn,
+            }
+        }
+        ( Command::Drop(index), true ) => {
+            if index < game.inventory.len() {
+                drop_item(index, game, world);
+            }
+            DidntTakeTurn
+        }
+        ( Command::Descend, true ) => {
+            let player_on_stairs = world.positions.at(world.positions[PLAYER]).iter()
+                .any(|&id| world.stairs.contains(id));
+            if player_on_stairs {
+                next_level(game, world);
+            }
+            DidntTakeTurn
+        }
+        _ => DidntTakeTurn,
+    }
+}
+
+/// Runs one full turn for `command`: the player acts, then if that took the
+/// player's turn every monster does, as often as their speed allows, and
+/// every status counts down.
+pub fn play_turn( command: Command, game: &mut Game, world: &mut World ) -> PlayerAction {
+    let player_action = player_turn(command, game, world);
+    if player_action == PlayerAction::Exit {
+        return player_action;
+    }
+    update_fov(game, world);
+    if world.objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
+        let monsters: Vec<Entity> = world.ais.entities().collect();
+        for id in monsters {
+            for _ in 0..world.actions_in_turn(id, game.turn) {
+                // the dead lose their ai
+                if world.ais.contains(id) {
+                    ai_take_turn(id, game, world);
+                }
+            }
+        }
+        tick_statuses(game, world);
+        game.turn += 1;
+        update_fov(game, world);
+    }
+    player_action
+}
+
+/// The turn loop, returns once the front end asks to exit or the player dies.
+pub fn play_game( frontend: &mut dyn Frontend, game: &mut Game, world: &mut World ) {
+    while world.objects[PLAYER].alive {
+        let command = frontend.next_command(game, world);
+        if play_turn(command, game, world) == PlayerAction::Exit {
+            break;
+        }
+        while level_up_pending(world) {
+            match frontend.choose_level_up(game, world) {
+                Some(stat) => level_up(stat, game, world),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_game() -> (Game, World) {
+        new_game(7, Generator::default(), Templates::default())
+    }
+
+    fn spawn_orc( world: &mut World, pos: (i32,i32) ) -> Entity {
+        let mut orc = Object::new('o', "orc", WHITE, true);
+        orc.alive = true;
+        let orc = world.spawn(orc);
+        world.positions.insert(orc, pos);
+        world.ais.insert(orc, Ai::Basic);
+        world.fighters.insert(orc, Fighter {
+            base_max_hp: 10,
+            hp: 10,
+            base_defense: 0,
+            base_power: 3,
+            xp: 35,
+            level: 1,
+            on_death: DeathCallback::Monster,
+            on_hit: None,
+        });
+        orc
+    }
+
+    // a direction from the player with two tiles of open floor
+    fn open_direction( game: &Game, world: &World ) -> (i32,i32) {
+        let (x,y) = world.positions[PLAYER];
+        [(0,-1), (0,1), (-1,0), (1,0)].iter().copied()
+            .find(|&(dx,dy)| (1..=2).all(|n| !is_blocked(x+n*dx, y+n*dy, &game.map, world)))
+            .expect("the player starts in a room")
+    }
+
+    /// Plays a fixed list of commands, then exits.
+    struct Scripted {
+        commands: VecDeque<Command>,
+        asked: usize,
+    }
+
+    impl Frontend for Scripted {
+        fn next_command( &mut self, _game: &Game, _world: &World ) -> Command {
+            self.asked += 1;
+            self.commands.pop_front().unwrap_or(Command::Exit)
+        }
+
+        fn choose_level_up( &mut self, _game: &Game, _world: &World ) -> Option<Stat> {
+            Some(Stat::Constitution)
+        }
+    }
+
+    #[test]
+    fn only_commands_that_take_a_turn_move_the_clock() {
+        let (mut game, mut world) = test_game();
+        let (x,y) = world.positions[PLAYER];
+        let (dx,dy) = [(0,-1), (0,1), (-1,0), (1,0)].iter().copied()
+            .find(|&(dx,dy)| !is_blocked(x+dx, y+dy, &game.map, &world))
+            .expect("the player starts next to open floor");
+
+        assert_eq!(play_turn(Command::Move { dx, dy }, &mut game, &mut world), PlayerAction::TookTurn);
+        assert_eq!(world.positions[PLAYER], (x+dx, y+dy));
+        assert_eq!(game.turn, 1);
+
+        assert_eq!(play_turn(Command::Drop(99), &mut game, &mut world), PlayerAction::DidntTakeTurn);
+        assert_eq!(play_turn(Command::Descend, &mut game, &mut world), PlayerAction::DidntTakeTurn);
+        assert_eq!(game.turn, 1);
+
+        assert_eq!(play_turn(Command::Wait, &mut game, &mut world), PlayerAction::TookTurn);
+        assert_eq!(play_turn(Command::Exit, &mut game, &mut world), PlayerAction::Exit);
+        assert_eq!(game.turn, 2);
+    }
+
+    #[test]
+    fn descending_needs_the_stairs_marker_not_the_name() {
+        let (mut game, mut world) = test_game();
+        let (stairs, _) = world.stairs.iter().next().expect("every level has stairs");
+        let pos = world.positions[stairs];
+        world.objects[stairs].name = "ladder".into();
+        let fake = world.spawn(Object::new('>', "stairs", WHITE, false));
+        world.positions.insert(fake, world.positions[PLAYER]);
+
+        assert_eq!(play_turn(Command::Descend, &mut game, &mut world), PlayerAction::DidntTakeTurn);
+        assert_eq!(game.dungeon_level, 1);
+        world.positions.insert(PLAYER, pos);
+        play_turn(Command::Descend, &mut game, &mut world);
+        assert_eq!(game.dungeon_level, 2);
+        assert_eq!(world.stairs.iter().count(), 1);
+    }
+
+    #[test]
+    fn taking_off_a_max_hp_bonus_clamps_hp() {
+        let (mut game, mut world) = test_game();
+        let amulet = world.spawn(Object::new('"', "amulet", WHITE, false));
+        world.items.insert(amulet, Item::Equip);
+        world.equipment.insert(amulet, Equipment {
+            slot: Slot::Ring,
+            equipped: false,
+            max_hp_bonus: 10,
+            defense_bonus: 0,
+            power_bonus: 0,
+        });
+        game.inventory.push(amulet);
+        let index = game.inventory.len() - 1;
+        let base = world.max_hp(PLAYER, &game);
+        let toggle = Command::UseItem { index, target: None };
+
+        play_turn(toggle, &mut game, &mut world);
+        heal(PLAYER, 100, &game, &mut world);
+        play_turn(toggle, &mut game, &mut world);
+        assert_eq!(world.fighters[PLAYER].hp, base);
+
+        play_turn(toggle, &mut game, &mut world);
+        heal(PLAYER, 100, &game, &mut world);
+        assert_eq!(world.fighters[PLAYER].hp, base + 10);
+        play_turn(Command::Drop(index), &mut game, &mut world);
+        assert_eq!(world.fighters[PLAYER].hp, base);
+    }
+
+    #[test]
+    fn only_a_sleeper_that_survives_wakes_up() {
+        let (mut game, mut world) = test_game();
+        let orc = spawn_orc(&mut world, (0,0));
+        let woken = |game: &Game| game.messages.iter().filter(|(text, _)| text.contains("wakes up")).count();
+
+        apply_status(orc, Effect { status: Status::Sleep, turns: 10 }, &mut world);
+        assert_eq!(take_damage(orc, 4, &mut game, &mut world), None);
+        assert!(!world.has_status(orc, Status::Sleep));
+        assert_eq!(woken(&game), 1);
+
+        apply_status(orc, Effect { status: Status::Sleep, turns: 10 }, &mut world);
+        assert_eq!(take_damage(orc, 6, &mut game, &mut world), Some(35));
+        assert!(!world.objects[orc].alive);
+        assert_eq!(woken(&game), 1);
+    }
+
+    #[test]
+    fn the_dead_are_not_hurt_or_healed() {
+        let (mut game, mut world) = test_game();
+        let deaths = |game: &Game| game.messages.iter().filter(|(text, _)| text == "You dead!").count();
+
+        // killed by a monster while poisoned
+        apply_status(PLAYER, Effect { status: Status::Poison, turns: 5 }, &mut world);
+        assert_eq!(take_damage(PLAYER, 100, &mut game, &mut world), Some(0));
+        tick_statuses(&mut game, &mut world);
+        assert_eq!(deaths(&game), 1);
+
+        // killed by poison while regenerating
+        let (mut game, mut world) = test_game();
+        world.fighters[PLAYER].hp = 1;
+        apply_status(PLAYER, Effect { status: Status::Poison, turns: 5 }, &mut world);
+        apply_status(PLAYER, Effect { status: Status::Regeneration, turns: 5 }, &mut world);
+        tick_statuses(&mut game, &mut world);
+        assert!(!world.objects[PLAYER].alive);
+        assert_eq!(world.fighters[PLAYER].hp, 0);
+        assert_eq!(deaths(&game), 1);
+    }
+
+    #[test]
+    fn monsters_still_see_a_blind_player() {
+        let (mut game, mut world) = test_game();
+        let (x,y) = world.positions[PLAYER];
+        let (dx,dy) = open_direction(&game, &world);
+        let orc = spawn_orc(&mut world, (x+2*dx, y+2*dy));
+        apply_status(PLAYER, Effect { status: Status::Blindness, turns: 5 }, &mut world);
+        play_turn(Command::Wait, &mut game, &mut world);
+        assert!(!game.fov.is_in_fov(x+2*dx, y+2*dy), "the player sees next to nothing");
+        assert_eq!(world.positions[orc], (x+dx, y+dy), "the orc closes in");
+    }
+
+    #[test]
+    fn a_fireball_burns_everyone_in_reach_and_pays_only_for_monsters() {
+        let (mut game, mut world) = test_game();
+        let (x,y) = world.positions[PLAYER];
+        let (dx,dy) = open_direction(&game, &world);
+        let weak = spawn_orc(&mut world, (x+dx, y+dy));
+        let tough = spawn_orc(&mut world, (x+2*dx, y+2*dy));
+        world.fighters[tough].hp = 30;
+        let far = (x - dx*(FIREBALL_RADIUS + 2), y - dy*(FIREBALL_RADIUS + 2));
+        let distant = spawn_orc(&mut world, far);
+        let player_hp = world.fighters[PLAYER].hp;
+
+        let result = cast_fireball(0, Some((x+dx, y+dy)), &mut game, &mut world);
+        assert!(matches!(result, UseResult::UsedUp));
+        assert_eq!(world.fighters[PLAYER].hp, player_hp - FIREBALL_DAMAGE);
+        assert!(!world.objects[weak].alive);
+        assert_eq!(world.fighters[tough].hp, 30 - FIREBALL_DAMAGE);
+        assert_eq!(world.fighters[distant].hp, 10);
+        assert_eq!(world.fighters[PLAYER].xp, 35);
+    }
+
+    #[test]
+    fn targets_out_of_range_or_out_of_sight_are_cancelled() {
+        let (mut game, mut world) = test_game();
+        let (x,y) = world.positions[PLAYER];
+        let beyond_range = (0..MAP_WIDTH).flat_map(|tx| (0..MAP_HEIGHT).map(move |ty| (tx,ty)))
+            .find(|&pos| game.fov.is_in_fov(pos.0, pos.1) && distance((x,y), pos) > LIGHTNING_RANGE as f32)
+            .expect("the player can see further than a lightning bolt reaches");
+        let far = spawn_orc(&mut world, beyond_range);
+        assert!(matches!(cast_lightning(0, Some(beyond_range), &mut game, &mut world), UseResult::Cancelled));
+        assert_eq!(world.fighters[far].hp, 10);
+        assert_eq!(game.messages.iter().last().unwrap().0, "That is out of range.");
+
+        // in range, but the blind player cannot see that far
+        let (dx,dy) = open_direction(&game, &world);
+        let near = spawn_orc(&mut world, (x+2*dx, y+2*dy));
+        apply_status(PLAYER, Effect { status: Status::Blindness, turns: 5 }, &mut world);
+        update_fov(&mut game, &world);
+        assert!(matches!(cast_lightning(0, Some((x+2*dx, y+2*dy)), &mut game, &mut world), UseResult::Cancelled));
+        assert!(matches!(cast_fireball(0, Some((x+2*dx, y+2*dy)), &mut game, &mut world), UseResult::Cancelled));
+        assert_eq!(world.fighters[near].hp, 10);
+        assert_eq!(world.fighters[PLAYER].hp, world.max_hp(PLAYER, &game));
+
+        // and once it can, the same bolt strikes
+        remove_status(PLAYER, Status::Blindness, &mut world);
+        update_fov(&mut game, &world);
+        assert!(matches!(cast_lightning(0, Some((x+2*dx, y+2*dy)), &mut game, &mut world), UseResult::UsedUp));
+        assert!(!world.objects[near].alive);
+    }
+
+    #[test]
+    fn play_game_asks_the_frontend_until_it_exits() {
+        let (mut game, mut world) = test_game();
+        let mut frontend = Scripted { commands: vec![Command::Wait; 5].into(), asked: 0 };
+        play_game(&mut frontend, &mut game, &mut world);
+        assert_eq!(frontend.asked, 6);
+        assert_eq!(game.turn, 5);
+        assert!(world.objects[PLAYER].alive);
+    }
+
+    #[test]
+    fn the_same_seed_and_commands_give_the_same_game() {
+        let script = [
+            Command::Move { dx: 1, dy: 0 }, Command::Move { dx: 0, dy: 1 }, Command::Wait,
+            Command::Move { dx: -1, dy: -1 }, Command::PickUp, Command::Move { dx: 1, dy: 1 },
+            Command::Descend, Command::Move { dx: 0, dy: -1 }, Command::Wait,
+        ];
+        let run = |seed: u64| {
+            let (mut game, mut world) = new_game(seed, Generator::default(), Templates::default());
+            for _ in 0..20 {
+                for &command in &script {
+                    play_turn(command, &mut game, &mut world);
+                }
+            }
+            serde_json::to_string(&(&game, &world)).unwrap()
+        };
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+    }
+}
@@ -0,0 +1,439 @@
+use crate::{Map, Rect, Tile, MAP_HEIGHT, MAP_WIDTH};
+use rand::Rng;
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::fmt;
+
+// room sizes, walls included
+const ROOM_MAX_SIZE: i32 = 10;
+const ROOM_MIN_SIZE: i32 = 6;
+const MAX_ROOMS: i32 = 30;
+// BSP areas are never split below this, so every leaf has room for a room
+const BSP_MIN_LEAF: i32 = ROOM_MIN_SIZE + 2;
+// caves: chance of a wall in the first random fill, then smoothing passes
+const CAVE_FILL: f32 = 0.45;
+const CAVE_SMOOTHING: i32 = 5;
+// a tile turns to wall with this many walls in its 3x3 block
+const CAVE_WALL_THRESHOLD: usize = 5;
+// the main cavern must cover this much of the map or the cave is redone
+const CAVE_MIN_FLOOR: f32 = 0.35;
+// caves are split into square chunks to spread spawns like rooms do
+const CAVE_REGION_SIZE: i32 = 12;
+const CAVE_REGION_MIN_TILES: usize = 8;
+// a map needing more tunnels than this to join up is thrown away, until the last attempt
+const MAX_REPAIR_TUNNELS: usize = 8;
+const MAX_GENERATION_ATTEMPTS: u32 = 5;
+
+const NEIGHBOURS: [(i32,i32); 8] = [
+    (0,-1), (0,1), (-1,0), (1,0),
+    (-1,-1), (1,-1), (-1,1), (1,1),
+];
+
+/// How the layout of a level is laid out.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Serialize,Deserialize)]
+pub enum Generator {
+    /// Random non-overlapping rooms joined one after the other.
+    #[default]
+    Rooms,
+    /// Binary space partition, one room per leaf joined up the tree.
+    Bsp,
+    /// Cellular automata caverns.
+    Caves,
+}
+
+/// How generating a map went.
+#[derive(Clone,Copy,Debug,Default,PartialEq,Serialize,Deserialize)]
+pub struct MapStats {
+    pub floor_tiles: usize,
+    /// Floor that could not be reached from the start before any repair.
+    pub unreachable_tiles: usize,
+    pub tunnels_added: usize,
+    /// Maps carved, including the one kept.
+    pub attempts: u32,
+}
+
+impl MapStats {
+    pub fn repaired( &self ) -> bool {
+        self.tunnels_added > 0 || self.attempts > 1
+    }
+}
+
+impl fmt::Display for MapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "{} floor tiles, {} cut off, {} tunnels dug, {} attempts",
+            self.floor_tiles, self.unreachable_tiles, self.tunnels_added, self.attempts,
+        )
+    }
+}
+
+/// A part of the map that monsters and items are spread over.
+#[derive(Clone,Debug)]
+pub enum Region {
+    Room(Rect),
+    /// The floor tiles of a stretch of cave.
+    Cave(Vec<(i32,i32)>),
+}
+
+impl Region {
+    /// A floor tile near the middle.
+    pub fn center( &self ) -> (i32,i32) {
+        match self {
+            Region::Room(room) => room.center(),
+            Region::Cave(tiles) => {
+                let count = tiles.len() as i32;
+                let mean_x = tiles.iter().map(|&(x,_)| x).sum::<i32>() / count;
+                let mean_y = tiles.iter().map(|&(_,y)| y).sum::<i32>() / count;
+                *tiles.iter()
+                    .min_by_key(|&&(x,y)| (x-mean_x).pow(2) + (y-mean_y).pow(2))
+                    .unwrap()
+            }
+        }
+    }
+
+    /// A random floor tile, walls excluded.
+    pub fn random_tile( &self, rng: &mut Pcg32 ) -> (i32,i32) {
+        match self {
+            Region::Room(room) => {
+                let x = rng.gen_range(room.x1+1, room.x2);
+                let y = rng.gen_range(room.y1+1, room.y2);
+                (x,y)
+            }
+            Region::Cave(tiles) => tiles[rng.gen_range(0, tiles.len())],
+        }
+    }
+}
+
+impl Generator {
+    pub const ALL: [Generator; 3] = [Generator::Rooms, Generator::Bsp, Generator::Caves];
+
+    /// Carves a new map, returning it with its regions. The player starts in
+    /// the first region and the stairs go in the last. Every floor tile can
+    /// be reached from the start: floor that is cut off gets tunnelled to, or
+    /// the map is redone if that takes too many tunnels.
+    pub fn generate( self, rng: &mut Pcg32 ) -> (Map, Vec<Region>, MapStats) {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let (mut map, regions) = self.carve(rng);
+            let start = regions[0].center();
+            let unreachable_tiles = unreachable_floor(&map, start).len();
+            let max_tunnels = if attempts < MAX_GENERATION_ATTEMPTS { MAX_REPAIR_TUNNELS } else { usize::MAX };
+            if let Some(tunnels_added) = repair(&mut map, start, max_tunnels, rng) {
+                let floor_tiles = map.iter().flatten().filter(|tile| !tile.blocked).count();
+                let stats = MapStats { floor_tiles, unreachable_tiles, tunnels_added, attempts };
+                return (map, regions, stats);
+            }
+        }
+    }
+
+    fn carve( self, rng: &mut Pcg32 ) -> (Map, Vec<Region>) {
+        let (map, rooms) = match self {
+            Generator::Rooms => rooms(rng),
+            Generator::Bsp => bsp(rng),
+            Generator::Caves => return caves(rng),
+        };
+        (map, rooms.into_iter().map(Region::Room).collect())
+    }
+}
+
+impl fmt::Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Generator::Rooms => write!(f, "Rooms and corridors"),
+            Generator::Bsp => write!(f, "Binary space partition"),
+            Generator::Caves => write!(f, "Caves"),
+        }
+    }
+}
+
+fn create_room( room: Rect, map: &mut Map ) {
+    for x in (room.x1+1)..room.x2 {
+        for y in (room.y1+1)..room.y2 {
+            map[x as usize][y as usize] = Tile::empty();
+        }
+    }
+}
+
+fn create_h_tunnel( x1: i32, x2: i32, y: i32, map: &mut Map ) {
+    for x in cmp::min(x1,x2)..(cmp::max(x1,x2)+1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+fn create_v_tunnel( y1: i32, y2: i32, x: i32, map: &mut Map ) {
+    for y in cmp::min(y1,y2)..(cmp::max(y1,y2)+1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+/// An L-shaped tunnel between two points, coin toss on h-v or v-h.
+fn connect( (x1,y1): (i32,i32), (x2,y2): (i32,i32), map: &mut Map, rng: &mut Pcg32 ) {
+    if rng.gen() {
+        create_h_tunnel(x1, x2, y1, map);
+        create_v_tunnel(y1, y2, x2, map);
+    } else {
+        create_v_tunnel(y1, y2, x1, map);
+        create_h_tunnel(x1, x2, y2, map);
+    }
+}
+
+fn rooms( rng: &mut Pcg32 ) -> (Map, Vec<Rect>) {
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut rooms: Vec<Rect> = vec![];
+    for _ in 0..MAX_ROOMS {
+        let w = rng.gen_range(ROOM_MIN_SIZE,ROOM_MAX_SIZE+1);
+        let h = rng.gen_range(ROOM_MIN_SIZE,ROOM_MAX_SIZE+1);
+        let x = rng.gen_range(0,MAP_WIDTH-w);
+        let y = rng.gen_range(0,MAP_HEIGHT-h);
+        let new_room = Rect::new(x, y, w, h);
+        let failed = rooms.iter().any(|other_room| new_room.intersects_with(other_room));
+        if !failed {
+            create_room(new_room, &mut map);
+            // connect to the previous room
+            if let Some(previous) = rooms.last() {
+                connect(previous.center(), new_room.center(), &mut map, rng);
+            }
+            rooms.push(new_room);
+        }
+    }
+    (map, rooms)
+}
+
+fn bsp( rng: &mut Pcg32 ) -> (Map, Vec<Rect>) {
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut rooms = vec![];
+    // one column and row short, like `rooms`, so the map keeps a wall all round
+    let whole = Rect::new(0, 0, MAP_WIDTH-1, MAP_HEIGHT-1);
+    bsp_split(whole, &mut map, &mut rooms, rng);
+    (map, rooms)
+}
+
+/// Splits `area` in two until it is too small, puts a room in each leaf and
+/// joins the two halves at every level. Returns a point inside one of the
+/// rooms in `area` for the caller to join to.
+fn bsp_split( area: Rect, map: &mut Map, rooms: &mut Vec<Rect>, rng: &mut Pcg32 ) -> (i32,i32) {
+    let w = area.x2 - area.x1;
+    let h = area.y2 - area.y1;
+    let can_split_x = w >= 2*BSP_MIN_LEAF;
+    let can_split_y = h >= 2*BSP_MIN_LEAF;
+    let split_x = match (can_split_x, can_split_y) {
+        (false, false) => {
+            let room_w = rng.gen_range(ROOM_MIN_SIZE, cmp::min(ROOM_MAX_SIZE, w)+1);
+            let room_h = rng.gen_range(ROOM_MIN_SIZE, cmp::min(ROOM_MAX_SIZE, h)+1);
+            let x = rng.gen_range(area.x1, area.x2-room_w+1);
+            let y = rng.gen_range(area.y1, area.y2-room_h+1);
+            let room = Rect::new(x, y, room_w, room_h);
+            create_room(room, map);
+            rooms.push(room);
+            return room.center();
+        }
+        (true, false) => true,
+        (false, true) => false,
+        // cut across the longer side, keeps the leaves close to square
+        (true, true) => w >= h,
+    };
+    let (first, second) = if split_x {
+        let cut = rng.gen_range(area.x1+BSP_MIN_LEAF, area.x2-BSP_MIN_LEAF+1);
+        (Rect { x2: cut, ..area }, Rect { x1: cut, ..area })
+    } else {
+        let cut = rng.gen_range(area.y1+BSP_MIN_LEAF, area.y2-BSP_MIN_LEAF+1);
+        (Rect { y2: cut, ..area }, Rect { y1: cut, ..area })
+    };
+    let first_point = bsp_split(first, map, rooms, rng);
+    let second_point = bsp_split(second, map, rooms, rng);
+    connect(first_point, second_point, map, rng);
+    if rng.gen() { first_point } else { second_point }
+}
+
+fn on_map( (x,y): (i32,i32) ) -> bool {
+    (0..MAP_WIDTH).contains(&x) && (0..MAP_HEIGHT).contains(&y)
+}
+
+/// Which tiles can be walked to from `start`, indexed `[x][y]` like the map.
+pub fn flood_fill( map: &Map, start: (i32,i32) ) -> Vec<Vec<bool>> {
+    let mut reached = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    if map[start.0 as usize][start.1 as usize].blocked {
+        return reached;
+    }
+    reached[start.0 as usize][start.1 as usize] = true;
+    let mut open = vec![start];
+    while let Some((x,y)) = open.pop() {
+        for &(dx,dy) in NEIGHBOURS.iter() {
+            let next = (x+dx, y+dy);
+            if on_map(next)
+                && !map[next.0 as usize][next.1 as usize].blocked
+                && !reached[next.0 as usize][next.1 as usize] {
+                reached[next.0 as usize][next.1 as usize] = true;
+                open.push(next);
+            }
+        }
+    }
+    reached
+}
+
+fn caves( rng: &mut Pcg32 ) -> (Map, Vec<Region>) {
+    loop {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        // the outer edge stays solid
+        for x in 1..MAP_WIDTH-1 {
+            for y in 1..MAP_HEIGHT-1 {
+                if rng.gen::<f32>() >= CAVE_FILL {
+                    map[x as usize][y as usize] = Tile::empty();
+                }
+            }
+        }
+        for _ in 0..CAVE_SMOOTHING {
+            map = smooth_cave(&map);
+        }
+        if let Some(regions) = keep_largest_cavern(&mut map) {
+            return (map, regions);
+        }
+    }
+}
+
+fn smooth_cave( map: &Map ) -> Map {
+    let mut smoothed = map.clone();
+    for x in 1..MAP_WIDTH-1 {
+        for y in 1..MAP_HEIGHT-1 {
+            let walls = (-1..=1)
+                .flat_map(|dx| (-1..=1).map(move |dy| (x+dx, y+dy)))
+                .filter(|&(nx,ny)| map[nx as usize][ny as usize].blocked)
+                .count();
+            smoothed[x as usize][y as usize] = if walls >= CAVE_WALL_THRESHOLD {
+                Tile::wall()
+            } else {
+                Tile::empty()
+            };
+        }
+    }
+    smoothed
+}
+
+/// Walls up every pocket not joined to the biggest cavern and splits what is
+/// left into regions, `None` if the cavern is too small to be worth keeping.
+fn keep_largest_cavern( map: &mut Map ) -> Option<Vec<Region>> {
+    let mut seen = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut largest: Option<Vec<Vec<bool>>> = None;
+    let mut largest_size = 0;
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            if map[x as usize][y as usize].blocked || seen[x as usize][y as usize] {
+                continue;
+            }
+            let cavern = flood_fill(map, (x,y));
+            let mut size = 0;
+            for (seen_column, cavern_column) in seen.iter_mut().zip(cavern.iter()) {
+                for (seen_tile, &in_cavern) in seen_column.iter_mut().zip(cavern_column.iter()) {
+                    if in_cavern {
+                        *seen_tile = true;
+                        size += 1;
+                    }
+                }
+            }
+            if size > largest_size {
+                largest_size = size;
+                largest = Some(cavern);
+            }
+        }
+    }
+    let largest = largest?;
+    if (largest_size as f32) < CAVE_MIN_FLOOR * (MAP_WIDTH * MAP_HEIGHT) as f32 {
+        return None;
+    }
+    for (column, cavern_column) in map.iter_mut().zip(largest.iter()) {
+        for (tile, &in_cavern) in column.iter_mut().zip(cavern_column.iter()) {
+            if !in_cavern {
+                *tile = Tile::wall();
+            }
+        }
+    }
+
+    // left to right, so the player and the stairs end up on opposite sides
+    let mut regions = vec![];
+    for chunk_x in (0..MAP_WIDTH).step_by(CAVE_REGION_SIZE as usize) {
+        for chunk_y in (0..MAP_HEIGHT).step_by(CAVE_REGION_SIZE as usize) {
+            let tiles: Vec<(i32,i32)> = (chunk_x..cmp::min(chunk_x+CAVE_REGION_SIZE, MAP_WIDTH))
+                .flat_map(|x| (chunk_y..cmp::min(chunk_y+CAVE_REGION_SIZE, MAP_HEIGHT)).map(move |y| (x,y)))
+                .filter(|&(x,y)| !map[x as usize][y as usize].blocked)
+                .collect();
+            if tiles.len() >= CAVE_REGION_MIN_TILES {
+                regions.push(Region::Cave(tiles));
+            }
+        }
+    }
+    if regions.len() < 2 {
+        return None;
+    }
+    Some(regions)
+}
+
+fn unreachable_floor( map: &Map, start: (i32,i32) ) -> Vec<(i32,i32)> {
+    let reached = flood_fill(map, start);
+    (0..MAP_WIDTH)
+        .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x,y)))
+        .filter(|&(x,y)| !map[x as usize][y as usize].blocked && !reached[x as usize][y as usize])
+        .collect()
+}
+
+/// Tunnels from cut off floor to the nearest reachable tile until everything
+/// joins up. Returns how many tunnels it took, `None` if it needed more than
+/// `max_tunnels`.
+fn repair( map: &mut Map, start: (i32,i32), max_tunnels: usize, rng: &mut Pcg32 ) -> Option<usize> {
+    let mut tunnels = 0;
+    loop {
+        let reached = flood_fill(map, start);
+        let cut_off = (0..MAP_WIDTH)
+            .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x,y)))
+            .find(|&(x,y)| !map[x as usize][y as usize].blocked && !reached[x as usize][y as usize]);
+        let (x,y) = match cut_off {
+            Some(tile) => tile,
+            None => return Some(tunnels),
+        };
+        if tunnels >= max_tunnels {
+            return None;
+        }
+        let nearest = (0..MAP_WIDTH)
+            .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x,y)))
+            .filter(|&(rx,ry)| reached[rx as usize][ry as usize])
+            .min_by_key(|&(rx,ry)| (rx-x).abs() + (ry-y).abs())?;
+        connect((x,y), nearest, map, rng);
+        tunnels += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn every_generator_connects_all_its_floor() {
+        for generator in Generator::ALL.iter().copied() {
+            for seed in 0..20 {
+                let mut rng = Pcg32::seed_from_u64(seed);
+                let (map, regions, stats) = generator.generate(&mut rng);
+                let start = regions[0].center();
+                assert!(!map[start.0 as usize][start.1 as usize].blocked, "{} seed {}", generator, seed);
+                assert!(unreachable_floor(&map, start).is_empty(), "{} seed {}", generator, seed);
+                assert!(stats.floor_tiles > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn repair_tunnels_into_a_sealed_pocket() {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        create_room(Rect::new(2, 2, 8, 6), &mut map);
+        // a pocket with solid rock all round it
+        create_room(Rect::new(40, 20, 6, 6), &mut map);
+        let start = (5, 5);
+        assert_eq!(unreachable_floor(&map, start).len(), 25);
+
+        let mut rng = Pcg32::seed_from_u64(1);
+        assert_eq!(repair(&mut map.clone(), start, 0, &mut rng), None);
+        assert_eq!(repair(&mut map, start, 8, &mut rng), Some(1));
+        assert!(unreachable_floor(&map, start).is_empty());
+    }
+}
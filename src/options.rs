@@ -0,0 +1,73 @@
+//! Command line options and the files they point at, read the same way by
+//! every front end.
+
+use crate::keys::KeyBindings;
+use crate::templates::Templates;
+use std::str::FromStr;
+
+pub const KEYS_FILE: &str = "keys.json";
+pub const TEMPLATES_FILE: &str = "data/templates.json";
+// every run is recorded here, replay it with `--replay <file>`
+pub const REPLAY_FILE: &str = "replay.jsonl";
+
+/// What a front end is started with. Anything missing or unreadable falls
+/// back to its default, with a note on stderr.
+pub struct Options {
+    /// `--seed <n>`, the seed New Game starts from instead of a random one.
+    pub seed: Option<u64>,
+    /// `--message-limit <n>`, how many messages a run keeps.
+    pub message_limit: Option<usize>,
+    /// `--replay <file>`, watch a recorded run instead of playing.
+    pub replay: Option<String>,
+    /// `--replay-delay <ms>`, the time between turns of a replay.
+    pub replay_delay: Option<u64>,
+    /// From `--keys <file>` or `keys.json`.
+    pub keys: KeyBindings,
+    /// From `--templates <file>` or `data/templates.json`.
+    pub templates: Templates,
+}
+
+impl Options {
+    pub fn from_args() -> Self {
+        Options {
+            seed: value_from_args("--seed"),
+            message_limit: value_from_args("--message-limit"),
+            replay: value_from_args("--replay"),
+            replay_delay: value_from_args("--replay-delay"),
+            keys: load_key_bindings(),
+            templates: load_templates(),
+        }
+    }
+}
+
+/// The value following `flag` on the command line, e.g. `--seed 42`.
+pub fn value_from_args<T: FromStr>( flag: &str ) -> Option<T> {
+    let args: Vec<String> = std::env::args().collect();
+    let position = args.iter().position(|arg| arg == flag)?;
+    match args.get(position+1).map(|value| value.parse()) {
+        Some(Ok(value)) => Some(value),
+        _ => {
+            eprintln!("{} is missing a valid value, ignoring it.", flag);
+            None
+        }
+    }
+}
+
+/// Key bindings from `--keys <file>` or `keys.json`, the defaults if that fails.
+fn load_key_bindings() -> KeyBindings {
+    let path = value_from_args("--keys").unwrap_or_else(|| String::from(KEYS_FILE));
+    KeyBindings::load(&path).unwrap_or_else(|e| {
+        eprintln!("Could not load key bindings from {}: {}. Using the defaults.", path, e);
+        KeyBindings::default()
+    })
+}
+
+/// Monster and item templates from `--templates <file>` or `data/templates.json`,
+/// the built-in ones if that fails.
+fn load_templates() -> Templates {
+    let path = value_from_args("--templates").unwrap_or_else(|| String::from(TEMPLATES_FILE));
+    Templates::load(&path).unwrap_or_else(|e| {
+        eprintln!("Could not load templates from {}:\n{}\nUsing the built-in ones.", path, e);
+        Templates::default()
+    })
+}
@@ -0,0 +1,142 @@
+use crate::ecs::World;
+use crate::{Map, MAP_HEIGHT, MAP_WIDTH};
+use std::cmp::{self, Reverse};
+use std::collections::BinaryHeap;
+
+// step costs, diagonals are roughly sqrt(2) as long
+const STRAIGHT_COST: i32 = 10;
+const DIAGONAL_COST: i32 = 14;
+// chasing further than this around obstacles just pulls monsters across the map
+const MAX_PATH_COST: i32 = 25 * STRAIGHT_COST;
+
+const NEIGHBOURS: [(i32,i32); 8] = [
+    (0,-1), (0,1), (-1,0), (1,0),
+    (-1,-1), (1,-1), (-1,1), (1,1),
+];
+
+fn index( (x,y): (i32,i32) ) -> usize {
+    (x * MAP_HEIGHT + y) as usize
+}
+
+/// Octile distance, never more than the real cost of an 8-way walk.
+fn estimate( (x1,y1): (i32,i32), (x2,y2): (i32,i32) ) -> i32 {
+    let dx = (x1 - x2).abs();
+    let dy = (y1 - y2).abs();
+    STRAIGHT_COST * cmp::max(dx, dy) + (DIAGONAL_COST - STRAIGHT_COST) * cmp::min(dx, dy)
+}
+
+/// A* search from `from` to `to` stepping around walls and blocking objects.
+/// `to` itself may be occupied, it is usually whoever is being chased.
+/// Returns the first step of the cheapest path, `None` if there is no path
+/// short enough to be worth following.
+pub fn first_step( from: (i32,i32), to: (i32,i32), map: &Map, world: &World ) -> Option<(i32,i32)> {
+    if from == to {
+        return None;
+    }
+    let size = (MAP_WIDTH * MAP_HEIGHT) as usize;
+    let blocked = |pos: (i32,i32)| {
+        pos != to && (
+            map[pos.0 as usize][pos.1 as usize].blocked
+            || world.positions.at(pos).iter().any(|&id| world.objects[id].blocks)
+        )
+    };
+
+    let mut cost = vec![i32::MAX; size];
+    let mut came_from: Vec<Option<(i32,i32)>> = vec![None; size];
+    let mut open = BinaryHeap::new();
+    cost[index(from)] = 0;
+    open.push(Reverse((estimate(from, to), 0, from)));
+    while let Some(Reverse((_, current_cost, current))) = open.pop() {
+        if current == to {
+            // walk back to the step right after `from`
+            let mut step = current;
+            while let Some(previous) = came_from[index(step)] {
+                if previous == from {
+                    return Some((step.0 - from.0, step.1 - from.1));
+                }
+                step = previous;
+            }
+            return None;
+        }
+        if current_cost > cost[index(current)] {
+            // a cheaper way here was already expanded
+            continue;
+        }
+        for &(dx, dy) in NEIGHBOURS.iter() {
+            let next = (current.0 + dx, current.1 + dy);
+            let on_map = (0..MAP_WIDTH).contains(&next.0) && (0..MAP_HEIGHT).contains(&next.1);
+            if !on_map || blocked(next) {
+                continue;
+            }
+            let step_cost = if dx != 0 && dy != 0 { DIAGONAL_COST } else { STRAIGHT_COST };
+            let next_cost = current_cost + step_cost;
+            if next_cost > MAX_PATH_COST || next_cost >= cost[index(next)] {
+                continue;
+            }
+            cost[index(next)] = next_cost;
+            came_from[index(next)] = Some(current);
+            open.push(Reverse((next_cost + estimate(next, to), next_cost, next)));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::WHITE;
+    use crate::ecs::Entity;
+    use crate::{Object, Tile};
+
+    fn open_map() -> Map {
+        vec![vec![Tile::empty(); MAP_HEIGHT as usize]; MAP_WIDTH as usize]
+    }
+
+    fn blocker( world: &mut World, pos: (i32,i32) ) -> Entity {
+        let id = world.spawn(Object::new('o', "orc", WHITE, true));
+        world.positions.insert(id, pos);
+        id
+    }
+
+    #[test]
+    fn steps_around_a_wall_corner() {
+        let mut map = open_map();
+        // a wall running up from (6,5), the way round is below it
+        for tile in &mut map[6][..=5] {
+            *tile = Tile::wall();
+        }
+        let world = World::new();
+        assert_eq!(first_step((5,5), (7,5), &map, &world), Some((1,1)));
+    }
+
+    #[test]
+    fn steps_around_a_blocking_monster() {
+        let map = open_map();
+        let mut world = World::new();
+        blocker(&mut world, (6,5));
+        let step = first_step((5,5), (8,5), &map, &world);
+        assert!(matches!(step, Some((1,dy)) if dy != 0), "{:?}", step);
+    }
+
+    #[test]
+    fn the_target_may_stand_in_the_way() {
+        let map = open_map();
+        let mut world = World::new();
+        blocker(&mut world, (7,5));
+        assert_eq!(first_step((5,5), (7,5), &map, &world), Some((1,0)));
+        assert_eq!(first_step((6,5), (7,5), &map, &world), Some((1,0)));
+        assert_eq!(first_step((7,5), (7,5), &map, &world), None);
+    }
+
+    #[test]
+    fn too_long_a_path_falls_back_to_heading_straight() {
+        let map = open_map();
+        let mut world = World::new();
+        assert_eq!(first_step((1,1), (40,1), &map, &world), None);
+
+        let walker = world.spawn(Object::new('w', "walker", WHITE, true));
+        world.positions.insert(walker, (1,1));
+        crate::move_astar(walker, 40, 1, &map, &mut world);
+        assert_eq!(world.positions[walker], (2,1));
+    }
+}
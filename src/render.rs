@@ -0,0 +1,1156 @@
+//! Drawing shared by every front end. Screens are drawn through `Renderer`,
+//! which the tcod `Root` implements for the window (with the `tcod` feature)
+//! and `Buffer` implements in memory, for terminals to copy out and for tests
+//! to look at.
+
+use crate::colors::*;
+use crate::ecs::{join, Entity, World};
+use crate::keys::{Action, KeyBindings};
+use crate::mapgen::Generator;
+use crate::options::{Options, REPLAY_FILE};
+use crate::replay::{Recorder, Replay};
+use crate::*;
+use std::cmp;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+#[cfg(feature = "tcod")]
+use tcod::console::{BackgroundFlag, Console, Root};
+#[cfg(feature = "tcod")]
+use tcod::input::{self, Event, Key, KeyCode};
+
+// the panel along the bottom of the screen
+pub const PANEL_HEIGHT: i32 = 7;
+const BAR_WIDTH: i32 = 20;
+const MSG_X: i32 = BAR_WIDTH + 2;
+const MSG_HEIGHT: i32 = PANEL_HEIGHT - 1;
+// map colors
+const COLOR_DARK_WALL: Color = Color { r: 0, g: 0, b: 100 };
+const COLOR_LIGHT_WALL: Color = Color { r: 130, g: 110, b: 50 };
+const COLOR_DARK_GROUND: Color = Color { r: 50, g: 50, b: 150 };
+const COLOR_LIGHT_GROUND: Color = Color { r: 200, g: 180, b: 50 };
+// how much of the screen shows through behind a menu
+const MENU_BACKGROUND_ALPHA: f32 = 0.7;
+const INVENTORY_WIDTH: i32 = 50;
+const LEVEL_SCREEN_WIDTH: i32 = 40;
+const MAIN_MENU_WIDTH: i32 = 24;
+const COLOR_LOG_MATCH: Color = DARK_BLUE;
+const COLOR_VALID_TARGET: Color = LIGHT_GREEN;
+const COLOR_INVALID_TARGET: Color = LIGHT_RED;
+// time between turns in a replay, unless `--replay-delay <ms>` says otherwise
+const REPLAY_DELAY_MS: u64 = 200;
+// at no delay at all the replay would never be drawn or stopped
+const REPLAY_MIN_DELAY_MS: u64 = 1;
+const REPLAY_MAX_DELAY_MS: u64 = 3200;
+
+/// A key press, as far as the shared screens and key bindings need to tell
+/// them apart. `Char` is the character typed, so Shift makes it upper case.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum KeyPress {
+    Char(char),
+    Enter,
+    Escape,
+    Backspace,
+    Tab,
+    Insert,
+    Delete,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    NumPad(u8),
+    F(u8),
+    Other,
+}
+
+/// A key with the modifiers held along with it.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct KeyEvent {
+    pub key: KeyPress,
+    pub alt: bool,
+    pub ctrl: bool,
+}
+
+impl From<KeyPress> for KeyEvent {
+    fn from( key: KeyPress ) -> Self {
+        KeyEvent { key, alt: false, ctrl: false }
+    }
+}
+
+/// Whatever the player did next, for the screens that also take the mouse.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Input {
+    Key(KeyEvent),
+    /// The mouse is over cell `(x, y)`, with a button just pressed or not.
+    Mouse { x: i32, y: i32, left: bool, right: bool },
+}
+
+/// Somewhere to draw a screen of character cells and read keys from.
+pub trait Renderer {
+    fn width( &self ) -> i32;
+    fn height( &self ) -> i32;
+    /// Blanks the whole screen to black.
+    fn clear( &mut self );
+    /// Sets the glyph and its colour, the background stays.
+    fn put_char( &mut self, x: i32, y: i32, ch: char, fg: Color );
+    fn set_background( &mut self, x: i32, y: i32, bg: Color );
+    fn background( &self, x: i32, y: i32 ) -> Color;
+    /// Shows everything drawn since the last call.
+    fn present( &mut self );
+    fn wait_for_input( &mut self ) -> Input;
+    /// Like `wait_for_input`, but gives up with `None` after `timeout`.
+    fn poll_input( &mut self, timeout: Duration ) -> Option<Input>;
+
+    /// The next key, passing over the mouse.
+    fn wait_for_key_event( &mut self ) -> KeyEvent {
+        loop {
+            if let Input::Key(key) = self.wait_for_input() {
+                return key;
+            }
+        }
+    }
+
+    /// The next key without its modifiers, which is all menus look at.
+    fn wait_for_key( &mut self ) -> KeyPress {
+        self.wait_for_key_event().key
+    }
+
+    /// Whether the player has gone away, by closing the window or losing the
+    /// terminal. Screens that wait for a choice give up then.
+    fn closed( &self ) -> bool {
+        false
+    }
+
+    fn toggle_fullscreen( &mut self ) {}
+
+    /// Prints one line from `x`, anything off the screen is dropped.
+    fn print( &mut self, x: i32, y: i32, text: &str, fg: Color ) {
+        for (i, ch) in text.chars().enumerate() {
+            self.put_char(x + i as i32, y, ch, fg);
+        }
+    }
+
+    /// Blanks a rectangle to `bg`.
+    fn fill( &mut self, x: i32, y: i32, w: i32, h: i32, bg: Color ) {
+        for cy in y..y+h {
+            for cx in x..x+w {
+                self.put_char(cx, cy, ' ', WHITE);
+                self.set_background(cx, cy, bg);
+            }
+        }
+    }
+
+    /// Copies `panel` with its top left at `(x, y)`. Its backgrounds are
+    /// blended over what is there by `background_alpha`, 1.0 covers it.
+    fn blit( &mut self, panel: &Buffer, x: i32, y: i32, background_alpha: f32 ) {
+        for py in 0..panel.height {
+            for px in 0..panel.width {
+                let cell = panel.cells[(py * panel.width + px) as usize];
+                let (sx, sy) = (x + px, y + py);
+                if !(0..self.width()).contains(&sx) || !(0..self.height()).contains(&sy) {
+                    continue;
+                }
+                let bg = lerp(self.background(sx, sy), cell.bg, background_alpha);
+                self.put_char(sx, sy, cell.ch, cell.fg);
+                self.set_background(sx, sy, bg);
+            }
+        }
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: Color,
+    pub bg: Color,
+}
+
+const BLANK: Cell = Cell { ch: ' ', fg: WHITE, bg: BLACK };
+
+/// A screen kept in memory. Keys given to `with_keys` are handed out in
+/// order, then Escape, after which it reads as closed, so a screen waiting
+/// for input never hangs.
+#[derive(Clone,Debug)]
+pub struct Buffer {
+    width: i32,
+    height: i32,
+    cells: Vec<Cell>,
+    keys: VecDeque<Input>,
+    // waited for a key after the last one was handed out
+    ran_out: bool,
+    /// How many times `present` has been called.
+    pub presented: usize,
+}
+
+impl Buffer {
+    pub fn new( width: i32, height: i32 ) -> Self {
+        Buffer {
+            width,
+            height,
+            cells: vec![BLANK; (width * height) as usize],
+            keys: VecDeque::new(),
+            ran_out: false,
+            presented: 0,
+        }
+    }
+
+    pub fn with_keys( mut self, keys: &[KeyPress] ) -> Self {
+        self.keys.extend(keys.iter().map(|&key| Input::Key(key.into())));
+        self
+    }
+
+    /// Like `with_keys`, for keys with modifiers or the mouse.
+    pub fn with_inputs( mut self, inputs: &[Input] ) -> Self {
+        self.keys.extend(inputs);
+        self
+    }
+
+    pub fn cell( &self, x: i32, y: i32 ) -> Option<Cell> {
+        self.index(x, y).map(|index| self.cells[index])
+    }
+
+    /// The glyphs along row `y`.
+    pub fn row_text( &self, y: i32 ) -> String {
+        (0..self.width).filter_map(|x| self.cell(x, y)).map(|cell| cell.ch).collect()
+    }
+
+    /// Whether `text` appears on any one row.
+    pub fn contains_text( &self, text: &str ) -> bool {
+        (0..self.height).any(|y| self.row_text(y).contains(text))
+    }
+
+    fn index( &self, x: i32, y: i32 ) -> Option<usize> {
+        if (0..self.width).contains(&x) && (0..self.height).contains(&y) {
+            Some((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl Renderer for Buffer {
+    fn width( &self ) -> i32 {
+        self.width
+    }
+
+    fn height( &self ) -> i32 {
+        self.height
+    }
+
+    fn clear( &mut self ) {
+        for cell in self.cells.iter_mut() {
+            *cell = BLANK;
+        }
+    }
+
+    fn put_char( &mut self, x: i32, y: i32, ch: char, fg: Color ) {
+        if let Some(index) = self.index(x, y) {
+            self.cells[index].ch = ch;
+            self.cells[index].fg = fg;
+        }
+    }
+
+    fn set_background( &mut self, x: i32, y: i32, bg: Color ) {
+        if let Some(index) = self.index(x, y) {
+            self.cells[index].bg = bg;
+        }
+    }
+
+    fn background( &self, x: i32, y: i32 ) -> Color {
+        self.cell(x, y).map_or(BLACK, |cell| cell.bg)
+    }
+
+    fn present( &mut self ) {
+        self.presented += 1;
+    }
+
+    fn wait_for_input( &mut self ) -> Input {
+        self.keys.pop_front().unwrap_or_else(|| {
+            self.ran_out = true;
+            Input::Key(KeyPress::Escape.into())
+        })
+    }
+
+    /// Never waits, with no keys left the time is simply up.
+    fn poll_input( &mut self, _timeout: Duration ) -> Option<Input> {
+        self.keys.pop_front()
+    }
+
+    fn closed( &self ) -> bool {
+        self.ran_out
+    }
+}
+
+#[cfg(feature = "tcod")]
+impl Renderer for Root {
+    fn width( &self ) -> i32 {
+        Console::width(self)
+    }
+
+    fn height( &self ) -> i32 {
+        Console::height(self)
+    }
+
+    fn clear( &mut self ) {
+        self.set_default_background(BLACK.into());
+        Console::clear(self);
+    }
+
+    fn put_char( &mut self, x: i32, y: i32, ch: char, fg: Color ) {
+        if (0..Renderer::width(self)).contains(&x) && (0..Renderer::height(self)).contains(&y) {
+            self.set_char(x, y, ch);
+            self.set_char_foreground(x, y, fg.into());
+        }
+    }
+
+    fn set_background( &mut self, x: i32, y: i32, bg: Color ) {
+        if (0..Renderer::width(self)).contains(&x) && (0..Renderer::height(self)).contains(&y) {
+            self.set_char_background(x, y, bg.into(), BackgroundFlag::Set);
+        }
+    }
+
+    fn background( &self, x: i32, y: i32 ) -> Color {
+        self.get_char_background(x, y).into()
+    }
+
+    fn present( &mut self ) {
+        self.flush();
+    }
+
+    fn wait_for_input( &mut self ) -> Input {
+        loop {
+            if let Some(input) = next_input(self) {
+                return input;
+            }
+            // flushing waits for the next frame rather than spinning
+            self.flush();
+        }
+    }
+
+    fn poll_input( &mut self, timeout: Duration ) -> Option<Input> {
+        let started = Instant::now();
+        loop {
+            if let Some(input) = next_input(self) {
+                return Some(input);
+            }
+            if started.elapsed() >= timeout {
+                return None;
+            }
+            self.flush();
+        }
+    }
+
+    fn closed( &self ) -> bool {
+        self.window_closed()
+    }
+
+    fn toggle_fullscreen( &mut self ) {
+        let fullscreen = self.is_fullscreen();
+        self.set_fullscreen(!fullscreen);
+    }
+}
+
+/// The first queued event the shared screens care about, Escape once the
+/// window is closed.
+#[cfg(feature = "tcod")]
+fn next_input( root: &Root ) -> Option<Input> {
+    loop {
+        match input::check_for_event(input::KEY_PRESS | input::MOUSE) {
+            Some((_, Event::Key(key))) => {
+                // a typed character comes as a key and as text, and one
+                // of the two means nothing, see `key_event`
+                let event = key_event(&key);
+                if event.key != KeyPress::Other {
+                    return Some(Input::Key(event));
+                }
+            }
+            Some((_, Event::Mouse(mouse))) => return Some(Input::Mouse {
+                x: mouse.cx as i32,
+                y: mouse.cy as i32,
+                left: mouse.lbutton_pressed,
+                right: mouse.rbutton_pressed,
+            }),
+            None if root.window_closed() => return Some(Input::Key(KeyPress::Escape.into())),
+            None => return None,
+        }
+    }
+}
+
+/// What a tcod key event means to the shared screens. tcod reports a typed
+/// character twice, as the key and as text: letters and digits are taken from
+/// the key, everything else from the text.
+#[cfg(feature = "tcod")]
+pub fn key_event( key: &Key ) -> KeyEvent {
+    use KeyCode::*;
+    let press = match key.code {
+        Escape => KeyPress::Escape,
+        Enter | NumPadEnter => KeyPress::Enter,
+        Backspace => KeyPress::Backspace,
+        Tab => KeyPress::Tab,
+        Insert => KeyPress::Insert,
+        Delete => KeyPress::Delete,
+        Up => KeyPress::Up,
+        Down => KeyPress::Down,
+        Left => KeyPress::Left,
+        Right => KeyPress::Right,
+        Home => KeyPress::Home,
+        End => KeyPress::End,
+        PageUp => KeyPress::PageUp,
+        PageDown => KeyPress::PageDown,
+        NumPad0 => KeyPress::NumPad(0),
+        NumPad1 => KeyPress::NumPad(1),
+        NumPad2 => KeyPress::NumPad(2),
+        NumPad3 => KeyPress::NumPad(3),
+        NumPad4 => KeyPress::NumPad(4),
+        NumPad5 => KeyPress::NumPad(5),
+        NumPad6 => KeyPress::NumPad(6),
+        NumPad7 => KeyPress::NumPad(7),
+        NumPad8 => KeyPress::NumPad(8),
+        NumPad9 => KeyPress::NumPad(9),
+        F1 => KeyPress::F(1),
+        F2 => KeyPress::F(2),
+        F3 => KeyPress::F(3),
+        F4 => KeyPress::F(4),
+        F5 => KeyPress::F(5),
+        F6 => KeyPress::F(6),
+        F7 => KeyPress::F(7),
+        F8 => KeyPress::F(8),
+        F9 => KeyPress::F(9),
+        F10 => KeyPress::F(10),
+        F11 => KeyPress::F(11),
+        F12 => KeyPress::F(12),
+        Text => match key.text().chars().next() {
+            Some(c) if !c.is_ascii_alphanumeric() => KeyPress::Char(c),
+            _ => KeyPress::Other,
+        },
+        _ if key.printable.is_ascii_alphabetic() => {
+            let c = if key.shift { key.printable.to_ascii_uppercase() } else { key.printable };
+            KeyPress::Char(c)
+        }
+        // with Shift the digit keys type symbols, which come as text
+        _ if key.printable.is_ascii_digit() && !key.shift => KeyPress::Char(key.printable),
+        _ => KeyPress::Other,
+    };
+    KeyEvent { key: press, alt: key.alt, ctrl: key.ctrl }
+}
+
+fn lerp( from: Color, to: Color, amount: f32 ) -> Color {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+    Color { r: mix(from.r, to.r), g: mix(from.g, to.g), b: mix(from.b, to.b) }
+}
+
+/// Splits `text` into lines of at most `width` characters, at spaces where it can.
+pub fn wrap( text: &str, width: usize ) -> Vec<String> {
+    let mut lines = vec![];
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(line);
+                line = String::new();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// The map and what can be seen on it, with the panel along the bottom.
+pub fn render_all<R: Renderer + ?Sized>( renderer: &mut R, game: &Game, world: &World ) {
+    renderer.clear();
+    //draw the map tiles as background color
+    for y in 0..MAP_HEIGHT {
+        for x in 0..MAP_WIDTH {
+            let tile = game.map[x as usize][y as usize];
+            if !tile.explored {
+                continue;
+            }
+            let visible = game.fov.is_in_fov(x, y);
+            let color = match ( visible, tile.block_sight ) {
+                (false, true) => COLOR_DARK_WALL,
+                (false, false) => COLOR_DARK_GROUND,
+                (true, true) => COLOR_LIGHT_WALL,
+                (true, false) => COLOR_LIGHT_GROUND,
+            };
+            renderer.set_background(x, y, color);
+        }
+    }
+    //draw the objects
+    let mut to_draw: Vec<_> = join(&world.objects, &world.positions)
+        .filter( |&(_, o, &(x,y))| {
+            game.fov.is_in_fov(x,y)
+                || (o.always_visible && game.map[x as usize][y as usize].explored)
+        })
+        .collect();
+    to_draw.sort_by_key(|&(_, o, _)| o.blocks);
+    for &(_, object, &(x,y)) in &to_draw {
+        renderer.put_char(x, y, object.char, object.color);
+    }
+
+    let mut panel = Buffer::new(renderer.width(), PANEL_HEIGHT);
+    let hp = world.fighters.get(PLAYER).map_or(0, |f| f.hp);
+    let max_hp = world.max_hp(PLAYER, game);
+    render_bar(&mut panel, 1, 1, BAR_WIDTH, "HP", hp, max_hp, LIGHT_RED, DARK_RED);
+    panel.print(1, 3, &format!("Dungeon level: {}", game.dungeon_level), WHITE);
+    let (level, xp) = world.fighters.get(PLAYER).map_or((1, 0), |f| (f.level, f.xp));
+    panel.print(1, 4, &format!("Level {}, XP: {}/{}", level, xp, level_up_xp(level)), WHITE);
+    // the player's statuses below that, as many as fit beside the messages
+    let effects = world.statuses.get(PLAYER).map_or(&[][..], |effects| &effects[..]);
+    let (mut x, mut y) = (1, 5);
+    for (i, effect) in effects.iter().enumerate() {
+        let text = format!("{} {}", effect.status.label(), effect.turns);
+        // the bottom row keeps room to say how many more there are
+        let hidden = effects.len() - i - 1;
+        let reserved = |y: i32| if y == PANEL_HEIGHT - 1 && hidden > 0 { format!(" +{}", hidden).len() } else { 0 };
+        if x > 1 && x + (text.len() + reserved(y)) as i32 > MSG_X - 1 {
+            if y == PANEL_HEIGHT - 1 {
+                panel.print(x, y, &format!("+{}", hidden + 1), WHITE);
+                break;
+            }
+            x = 1;
+            y += 1;
+        }
+        panel.print(x, y, &text, effect.status.color());
+        x += text.len() as i32 + 1;
+    }
+    // newest at the bottom, older messages above it while they fit
+    let msg_width = panel.width() - MSG_X;
+    let mut y = MSG_HEIGHT;
+    for (msg, color) in game.messages.iter().rev() {
+        let lines = wrap(msg, msg_width as usize);
+        y -= lines.len() as i32;
+        if y < 0 {
+            break;
+        }
+        for (row, line) in lines.iter().enumerate() {
+            panel.print(MSG_X, y + row as i32, line, *color);
+        }
+    }
+    let panel_y = renderer.height() - PANEL_HEIGHT;
+    renderer.blit(&panel, 0, panel_y, 1.0);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn render_bar<R: Renderer + ?Sized>(
+    renderer: &mut R,
+    x: i32,
+    y: i32,
+    total_width: i32,
+    name: &str,
+    value: i32,
+    maximum: i32,
+    bar_color: Color,
+    back_color: Color,
+) {
+    let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
+    renderer.fill(x, y, total_width, 1, back_color);
+    if bar_width > 0 {
+        renderer.fill(x, y, bar_width, 1, bar_color);
+    }
+    let text = format!("{}: {}/{}", name, value, maximum);
+    let text_x = x + (total_width - text.chars().count() as i32) / 2;
+    renderer.print(text_x, y, &text, WHITE);
+}
+
+/// Shows lettered options over the middle of the screen and waits for one to
+/// be picked, `None` for any other key.
+pub fn menu<R: Renderer + ?Sized, T: AsRef<str>>(
+    renderer: &mut R,
+    header: &str,
+    options: &[T],
+    width: i32,
+) -> Option<usize> {
+    assert!( options.len() <= 26, "Cannot have menu with more than 26 options.");
+    let header_lines = if header.is_empty() { vec![] } else { wrap(header, width as usize) };
+    let header_height = header_lines.len() as i32;
+    let height = options.len() as i32 + header_height;
+    let mut window = Buffer::new(width, height);
+    for (row, line) in header_lines.iter().enumerate() {
+        window.print(0, row as i32, line, WHITE);
+    }
+    for (index, option_text) in options.iter().enumerate() {
+        let menu_letter = (b'a' + index as u8) as char;
+        let text = format!("({}) {}", menu_letter, option_text.as_ref());
+        window.print(0, header_height + index as i32, &text, WHITE);
+    }
+    let x = renderer.width()/2 - width/2;
+    let y = renderer.height()/2 - height/2;
+    renderer.blit(&window, x, y, MENU_BACKGROUND_ALPHA);
+    renderer.present();
+    match renderer.wait_for_key() {
+        KeyPress::Char(c) if c.is_ascii_alphabetic() => {
+            let index = c.to_ascii_lowercase() as usize - 'a' as usize;
+            if index < options.len() { Some(index) } else { None }
+        }
+        _ => None,
+    }
+}
+
+pub fn msgbox<R: Renderer + ?Sized>( renderer: &mut R, text: &str, width: i32 ) {
+    let options: &[&str] = &[];
+    menu(renderer, text, options, width);
+}
+
+/// Lists the inventory with what is worn marked, `None` if it is empty or
+/// nothing was picked.
+pub fn inventory_menu<R: Renderer + ?Sized>(
+    renderer: &mut R,
+    inventory: &[Entity],
+    world: &World,
+    header: &str,
+    width: i32,
+) -> Option<usize> {
+    let options = if inventory.is_empty() {
+        vec!["Inventory is empty.".into()]
+    } else {
+        inventory.iter().map(|&item| {
+            let name = &world.objects[item].name;
+            match world.equipment.get(item) {
+                Some(equipment) if equipment.equipped => {
+                    format!("{} (on {})", name, equipment.slot)
+                }
+                _ => name.clone(),
+            }
+        }).collect()
+    };
+    let inventory_index = menu(renderer, header, &options, width);
+    if !inventory.is_empty() {
+        inventory_index
+    } else {
+        None
+    }
+}
+
+/// Full screen view of the whole message history. Arrows and Page Up/Down
+/// scroll, Home/End jump to either end, `/` searches older messages and `n`/`N`
+/// repeat the search towards older/newer ones, Esc leaves.
+pub fn message_log<R: Renderer + ?Sized>( renderer: &mut R, messages: &Messages ) {
+    // one row each for the title and the key help
+    let page = renderer.height() - 2;
+    let total = messages.len() as i32;
+    let last_top = cmp::max(total - page, 0);
+    let mut top = last_top;
+    let mut query = String::new();
+    let mut typing = false;
+    let mut matched: Option<usize> = None;
+    while !renderer.closed() {
+        renderer.clear();
+        renderer.print(0, 0,
+            &format!("Message log ({}-{} of {})", top+1, cmp::min(top+page, total), total), WHITE);
+        for (row, (msg, color)) in messages.iter().skip(top as usize).take(page as usize).enumerate() {
+            let y = row as i32 + 1;
+            if matched == Some(top as usize + row) {
+                let width = renderer.width();
+                renderer.fill(0, y, width, 1, COLOR_LOG_MATCH);
+            }
+            renderer.print(0, y, msg, *color);
+        }
+        let help = if typing {
+            format!("Search: {}_", query)
+        } else {
+            "Arrows/PgUp/PgDn/Home/End scroll, / search, n/N next older/newer, Esc close".into()
+        };
+        let help_y = renderer.height() - 1;
+        renderer.print(0, help_y, &help, LIGHT_GREY);
+        renderer.present();
+
+        let key = renderer.wait_for_key();
+        let mut search_backwards = None;
+        if typing {
+            match key {
+                KeyPress::Enter => {
+                    typing = false;
+                    search_backwards = Some(true);
+                }
+                KeyPress::Escape => typing = false,
+                KeyPress::Backspace => {
+                    query.pop();
+                }
+                KeyPress::Char(c) => query.push(c),
+                _ => {}
+            }
+        } else {
+            match key {
+                KeyPress::Escape => break,
+                KeyPress::Up => top -= 1,
+                KeyPress::Down => top += 1,
+                KeyPress::PageUp => top -= page,
+                KeyPress::PageDown => top += page,
+                KeyPress::Home => top = 0,
+                KeyPress::End => top = last_top,
+                KeyPress::Char('/') => {
+                    typing = true;
+                    query.clear();
+                    matched = None;
+                }
+                KeyPress::Char('n') => search_backwards = Some(true),
+                KeyPress::Char('N') => search_backwards = Some(false),
+                _ => {}
+            }
+        }
+        if let Some(backwards) = search_backwards {
+            if !query.is_empty() {
+                // continue from the last hit, or from the bottom of the page
+                let start = match (matched, backwards) {
+                    (Some(index), true) if index > 0 => Some(index - 1),
+                    (Some(_), true) => None,
+                    (Some(index), false) => Some(index + 1),
+                    (None, _) => Some(cmp::max(cmp::min(top + page, total) - 1, 0) as usize),
+                };
+                matched = start.and_then(|start| messages.find(&query, start, backwards)).or(matched);
+                if let Some(index) = matched {
+                    // bring the hit into view
+                    let index = index as i32;
+                    if index < top || index >= top + page {
+                        top = index - page/2;
+                    }
+                }
+            }
+        }
+        top = top.clamp(0, last_top);
+    }
+}
+
+/// Lets the player pick a tile within `max_range`. The cursor moves with
+/// whatever keys move the player, or follows the mouse; Enter or a left click
+/// confirms, Esc or a right click cancels.
+pub fn target_tile<R: Renderer + ?Sized>(
+    renderer: &mut R,
+    keys: &KeyBindings,
+    game: &Game,
+    world: &World,
+    max_range: i32,
+) -> Option<(i32,i32)> {
+    let mut cursor = closest_monster(game, world, max_range)
+        .map_or(world.positions[PLAYER], |id| world.positions[id]);
+    while !renderer.closed() {
+        let valid = is_valid_target(game, world, cursor, max_range);
+        render_all(renderer, game, world);
+        let color = if valid { COLOR_VALID_TARGET } else { COLOR_INVALID_TARGET };
+        renderer.set_background(cursor.0, cursor.1, color);
+        renderer.print(0, 0, "Aim with the movement keys or mouse, Enter/click to confirm, Esc to cancel.", WHITE);
+        renderer.present();
+        match renderer.wait_for_input() {
+            Input::Mouse { x, y, left, right } => {
+                if x < MAP_WIDTH && y < MAP_HEIGHT {
+                    cursor = (x, y);
+                }
+                if left && is_valid_target(game, world, cursor, max_range) {
+                    return Some(cursor);
+                }
+                if right {
+                    return None;
+                }
+            }
+            Input::Key(key) => match key.key {
+                KeyPress::Escape => return None,
+                KeyPress::Enter if valid => return Some(cursor),
+                _ => if let Some((dx, dy)) = keys.action(key).and_then(Action::direction) {
+                    cursor.0 = (cursor.0 + dx).clamp(0, MAP_WIDTH - 1);
+                    cursor.1 = (cursor.1 + dy).clamp(0, MAP_HEIGHT - 1);
+                },
+            },
+        }
+    }
+    None
+}
+
+/// Asks which stat to raise until one is picked, `None` only if the player
+/// goes away first.
+pub fn choose_level_up<R: Renderer + ?Sized>( renderer: &mut R, game: &Game, world: &World ) -> Option<Stat> {
+    let fighter = *world.fighters.get(PLAYER)?;
+    let options = [
+        format!("Constitution (+20 HP, from {})", fighter.base_max_hp),
+        format!("Strength (+1 attack, from {})", fighter.base_power),
+        format!("Agility (+1 defense, from {})", fighter.base_defense),
+    ];
+    while !renderer.closed() {
+        render_all(renderer, game, world);
+        match menu(renderer, "Level up! Choose a stat to raise:\n", &options, LEVEL_SCREEN_WIDTH) {
+            Some(0) => return Some(Stat::Constitution),
+            Some(1) => return Some(Stat::Strength),
+            Some(2) => return Some(Stat::Agility),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A front end made of any `Renderer` and the options it was started with,
+/// reading commands through the key bindings. The window and the terminal
+/// are both one.
+pub struct Screen<R> {
+    pub renderer: R,
+    pub options: Options,
+}
+
+impl<R: Renderer> Screen<R> {
+    fn handle_keys( &mut self, game: &Game, world: &World ) -> Option<Command> {
+        use Command::*;
+        let key = self.renderer.wait_for_key_event();
+        if self.renderer.closed() {
+            return Some(Exit);
+        }
+        let renderer = &mut self.renderer;
+        let player_alive = world.objects[PLAYER].alive;
+        match ( self.options.keys.action(key)?, player_alive ) {
+            ( Action::Exit, _ ) => Some(Exit),
+            ( Action::Fullscreen, _ ) => {
+                renderer.toggle_fullscreen();
+                None
+            }
+            ( Action::MessageLog, _ ) => {
+                message_log(renderer, &game.messages);
+                None
+            }
+
+            ( Action::PickUp, true ) => Some(PickUp),
+            ( Action::Inventory, true ) => {
+                let index = inventory_menu(renderer, &game.inventory, world, "Select an item to use it, or any other to cancel.\n", INVENTORY_WIDTH)?;
+                let target = match world.items.get(game.inventory[index]).copied().and_then(Item::target_range) {
+                    Some(max_range) => target_tile(renderer, &self.options.keys, game, world, max_range),
+                    None => None,
+                };
+                Some(UseItem { index, target })
+            }
+            ( Action::Drop, true ) => {
+                let index = inventory_menu(renderer, &game.inventory, world, "Select an item to drop it, or any other to cancel.\n", INVENTORY_WIDTH)?;
+                Some(Drop(index))
+            }
+            ( Action::Descend, true ) => Some(Descend),
+            ( Action::Wait, true ) => Some(Wait),
+            ( action, true ) => {
+                let (dx, dy) = action.direction()?;
+                Some(Move { dx, dy })
+            }
+
+            _ => None
+        }
+    }
+}
+
+impl<R: Renderer> Frontend for Screen<R> {
+    fn next_command( &mut self, game: &Game, world: &World ) -> Command {
+        loop {
+            render_all(&mut self.renderer, game, world);
+            self.renderer.present();
+            if let Some(command) = self.handle_keys(game, world) {
+                return command;
+            }
+        }
+    }
+
+    fn choose_level_up( &mut self, game: &Game, world: &World ) -> Option<Stat> {
+        choose_level_up(&mut self.renderer, game, world)
+    }
+}
+
+/// Plays a run, recording it to a new replay file when `fresh`, or carrying
+/// on the existing one for a continued game. The run is saved unless the
+/// player died.
+pub fn run_game<R: Renderer>( screen: &mut Screen<R>, game: &mut Game, world: &mut World, fresh: bool ) {
+    if let Some(limit) = screen.options.message_limit {
+        game.messages.set_limit(limit);
+    }
+    let recorder = if fresh {
+        Recorder::create(REPLAY_FILE, game, &mut *screen).map_err(Into::into)
+    } else {
+        Recorder::append(REPLAY_FILE, game, &mut *screen)
+    };
+    match recorder {
+        Ok(mut recorder) => play_game(&mut recorder, game, world),
+        Err(e) => {
+            let text = format!("\nNot recording this session: {}\n", e);
+            msgbox(&mut screen.renderer, &text, LEVEL_SCREEN_WIDTH);
+            play_game(screen, game, world);
+        }
+    }
+    if world.objects[PLAYER].alive {
+        // leaving the loop by Esc or by closing the window both keep the run
+        if let Err(e) = save_game(game, world) {
+            let text = format!("\nCould not save game: {}\n", e);
+            msgbox(&mut screen.renderer, &text, LEVEL_SCREEN_WIDTH);
+        }
+    } else {
+        // a dead run has nothing left to continue
+        let _ = delete_save_game();
+        render_all(&mut screen.renderer, game, world);
+        msgbox(&mut screen.renderer, "\nYou died! Press any key to return to the menu.\n", MAIN_MENU_WIDTH);
+    }
+}
+
+/// Plays back a recorded session through the turn loop. `+`/`-` speed it up
+/// or slow it down, Space pauses and Esc stops.
+struct ReplayViewer<'a, R> {
+    renderer: &'a mut R,
+    replay: Replay,
+    delay: Duration,
+    paused: bool,
+}
+
+fn replay_delay( delay: Duration ) -> Duration {
+    delay.clamp(Duration::from_millis(REPLAY_MIN_DELAY_MS), Duration::from_millis(REPLAY_MAX_DELAY_MS))
+}
+
+impl<R: Renderer> ReplayViewer<'_, R> {
+    fn draw( &mut self, game: &Game, world: &World ) {
+        render_all(self.renderer, game, world);
+        let status = format!(
+            "Replay: {} inputs left, {} ms a turn{}. +/- speed, Space pause, Esc stop",
+            self.replay.remaining(), self.delay.as_millis(), if self.paused { ", paused" } else { "" },
+        );
+        let y = self.renderer.height() - PANEL_HEIGHT - 1;
+        self.renderer.print(0, y, &status, WHITE);
+        self.renderer.present();
+    }
+}
+
+impl<R: Renderer> Frontend for ReplayViewer<'_, R> {
+    fn next_command( &mut self, game: &Game, world: &World ) -> Command {
+        let started = Instant::now();
+        loop {
+            self.draw(game, world);
+            let input = if self.paused {
+                Some(self.renderer.wait_for_input())
+            } else {
+                match self.delay.checked_sub(started.elapsed()) {
+                    Some(left) if !left.is_zero() => self.renderer.poll_input(left),
+                    _ => None,
+                }
+            };
+            if self.renderer.closed() {
+                return Command::Exit;
+            }
+            match input {
+                // time for the next turn
+                None => break,
+                Some(Input::Key(key)) => match key.key {
+                    KeyPress::Escape => return Command::Exit,
+                    KeyPress::Char(' ') => self.paused = !self.paused,
+                    KeyPress::Char('+') | KeyPress::Char('=') => self.delay = replay_delay(self.delay / 2),
+                    KeyPress::Char('-') => self.delay = replay_delay(self.delay * 2),
+                    _ => {}
+                },
+                Some(Input::Mouse { .. }) => {}
+            }
+        }
+        self.replay.next_command()
+    }
+
+    fn choose_level_up( &mut self, _game: &Game, _world: &World ) -> Option<Stat> {
+        self.replay.next_level_up()
+    }
+}
+
+/// Replay mode, `--replay <file>` with `--replay-delay <ms>` between turns.
+pub fn watch_replay<R: Renderer>( screen: &mut Screen<R>, path: &str ) {
+    let replay = match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            let text = format!("\nCould not load replay {}: {}\n", path, e);
+            msgbox(&mut screen.renderer, &text, LEVEL_SCREEN_WIDTH);
+            return;
+        }
+    };
+    let (mut game, mut world) = replay.new_game();
+    let delay = screen.options.replay_delay.unwrap_or(REPLAY_DELAY_MS);
+    let mut viewer = ReplayViewer {
+        renderer: &mut screen.renderer,
+        replay,
+        delay: replay_delay(Duration::from_millis(delay)),
+        paused: false,
+    };
+    play_game(&mut viewer, &mut game, &mut world);
+    let left = viewer.replay.remaining();
+    render_all(viewer.renderer, &game, &world);
+    let text = if world.objects[PLAYER].alive {
+        format!("\nReplay over, {} inputs not played. Press any key.\n", left)
+    } else {
+        String::from("\nReplay over, the player died. Press any key.\n")
+    };
+    msgbox(viewer.renderer, &text, LEVEL_SCREEN_WIDTH);
+}
+
+/// New game, continue or quit, until the player quits or goes away.
+pub fn main_menu<R: Renderer>( screen: &mut Screen<R> ) {
+    while !screen.renderer.closed() {
+        let renderer = &mut screen.renderer;
+        let (width, height) = (renderer.width(), renderer.height());
+        renderer.clear();
+        let title = "TOMBS OF THE ANCIENT KINGS";
+        renderer.print((width - title.len() as i32) / 2, height/2 - 4, title, LIGHT_YELLOW);
+        let author = "By jimtahu";
+        renderer.print((width - author.len() as i32) / 2, height - 2, author, LIGHT_YELLOW);
+        let choices = &["Play a new game", "Continue last game", "Quit"];
+        match menu(renderer, "", choices, MAIN_MENU_WIDTH) {
+            Some(0) => {
+                // `--seed <n>` makes New Game start from the given seed
+                let seed = screen.options.seed.unwrap_or_else(rand::random);
+                let names: Vec<String> = Generator::ALL.iter().map(|generator| generator.to_string()).collect();
+                let choice = menu(renderer, "Choose a dungeon layout:\n", &names, LEVEL_SCREEN_WIDTH);
+                if let Some(generator) = choice.and_then(|index| Generator::ALL.get(index)) {
+                    let (mut game, mut world) = new_game(seed, *generator, screen.options.templates.clone());
+                    run_game(screen, &mut game, &mut world, true);
+                }
+            }
+            Some(1) => {
+                match load_game() {
+                    Ok((mut game, mut world)) => run_game(screen, &mut game, &mut world, false),
+                    Err(_e) => msgbox(renderer, "\nNo saved game to load.\n", MAIN_MENU_WIDTH),
+                }
+            }
+            Some(2) => break,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::templates::Templates;
+
+    fn test_game() -> (Game, World) {
+        new_game(3, Generator::default(), Templates::default())
+    }
+
+    fn options() -> Options {
+        Options {
+            seed: None,
+            message_limit: None,
+            replay: None,
+            replay_delay: None,
+            keys: KeyBindings::default(),
+            templates: Templates::default(),
+        }
+    }
+
+    #[test]
+    fn render_all_draws_the_map_and_the_panel() {
+        let (game, world) = test_game();
+        let mut screen = Buffer::new(MAP_WIDTH, MAP_HEIGHT + PANEL_HEIGHT);
+        render_all(&mut screen, &game, &world);
+        let (x, y) = world.positions[PLAYER];
+        assert_eq!(screen.cell(x, y).unwrap().ch, '@');
+        let hp = world.fighters[PLAYER].hp;
+        assert!(screen.contains_text(&format!("HP: {}/{}", hp, world.max_hp(PLAYER, &game))));
+        assert!(screen.row_text(MAP_HEIGHT + 3).contains("Dungeon level: 1"));
+        assert!(screen.contains_text("Welcome, stranger!"));
+    }
+
+    #[test]
+    fn statuses_that_do_not_fit_are_counted() {
+        use crate::status::{Effect, Status::*};
+        let (game, mut world) = test_game();
+        let all = [Poison, Regeneration, Haste, Slow, Sleep, Confusion, Blindness];
+        world.statuses.insert(PLAYER, all.iter().map(|&status| Effect { status, turns: 10 }).collect());
+        let mut screen = Buffer::new(MAP_WIDTH, MAP_HEIGHT + PANEL_HEIGHT);
+        render_all(&mut screen, &game, &world);
+        let rows: String = (5..PANEL_HEIGHT)
+            .map(|y| screen.row_text(MAP_HEIGHT + y).chars().take(MSG_X as usize).collect::<String>())
+            .collect();
+        let shown = all.iter().filter(|status| rows.contains(status.label())).count();
+        assert!(shown > 0 && shown < all.len());
+        assert!(rows.contains(&format!("+{}", all.len() - shown)), "{}", rows);
+    }
+
+    #[test]
+    fn menu_letters_pick_options() {
+        let options = ["First", "Second"];
+        let mut screen = Buffer::new(40, 20).with_keys(&[KeyPress::Char('b'), KeyPress::Char('c')]);
+        assert_eq!(menu(&mut screen, "Pick one:", &options, 20), Some(1));
+        assert!(screen.contains_text("Pick one:"));
+        assert!(screen.contains_text("(a) First"));
+        assert!(screen.contains_text("(b) Second"));
+        assert_eq!(menu(&mut screen, "", &options, 20), None);
+        assert_eq!(screen.presented, 2);
+    }
+
+    #[test]
+    fn inventory_menu_marks_what_is_worn() {
+        let (_, mut world) = test_game();
+        let mut screen = Buffer::new(MAP_WIDTH, 20).with_keys(&[KeyPress::Char('a')]);
+        assert_eq!(inventory_menu(&mut screen, &[], &world, "Use:", 50), None);
+        assert!(screen.contains_text("Inventory is empty."));
+
+        let sword = world.spawn(Object::new('/', "sword", SKY, false));
+        world.equipment.insert(sword, Equipment {
+            slot: Slot::MainHand,
+            equipped: true,
+            max_hp_bonus: 0,
+            defense_bonus: 0,
+            power_bonus: 3,
+        });
+        let mut screen = Buffer::new(MAP_WIDTH, 20).with_keys(&[KeyPress::Char('a')]);
+        assert_eq!(inventory_menu(&mut screen, &[sword], &world, "Use:", 50), Some(0));
+        assert!(screen.contains_text("(a) sword (on main hand)"));
+    }
+
+    #[test]
+    fn message_log_finds_and_highlights_a_message() {
+        let mut messages = Messages::new();
+        for i in 0..100 {
+            messages.add(format!("message {}", i), WHITE);
+        }
+        messages.add("the orc hits you", RED);
+        messages.add("you miss", WHITE);
+        let keys: Vec<KeyPress> = "/message 7".chars().map(KeyPress::Char)
+            .chain([KeyPress::Enter, KeyPress::Escape])
+            .collect();
+        let mut screen = Buffer::new(40, 12).with_keys(&keys);
+        message_log(&mut screen, &messages);
+        // the newest match, brought into view and highlighted
+        let row = (0..12).find(|&y| screen.row_text(y).starts_with("message 79")).unwrap();
+        assert_eq!(screen.cell(0, row).unwrap().bg, COLOR_LOG_MATCH);
+        assert!(!screen.contains_text("you miss"));
+    }
+
+    #[test]
+    fn screen_reads_commands_through_the_key_bindings() {
+        let (mut game, mut world) = test_game();
+        let keys = [KeyPress::Char('.'), KeyPress::Char('m'), KeyPress::Escape, KeyPress::Char('5'), KeyPress::Char('q')];
+        let mut screen = Screen { renderer: Buffer::new(MAP_WIDTH, MAP_HEIGHT + PANEL_HEIGHT).with_keys(&keys), options: options() };
+        play_game(&mut screen, &mut game, &mut world);
+        assert_eq!(game.turn, 2);
+        assert!(screen.renderer.contains_text("Dungeon level: 1"));
+    }
+
+    #[test]
+    fn a_level_up_with_no_keys_left_gives_up() {
+        let (mut game, mut world) = test_game();
+        world.fighters[PLAYER].xp = 1000;
+        let renderer = Buffer::new(MAP_WIDTH, MAP_HEIGHT + PANEL_HEIGHT).with_keys(&[KeyPress::Char('5')]);
+        let mut screen = Screen { renderer, options: options() };
+        assert!(!screen.renderer.closed());
+        play_game(&mut screen, &mut game, &mut world);
+        assert!(screen.renderer.closed());
+        assert_eq!(game.turn, 1);
+        assert_eq!(world.fighters[PLAYER].level, 1);
+    }
+
+    #[test]
+    fn watching_a_replay_plays_it_through() {
+        let path = std::env::temp_dir().join(format!("render-replay-test-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let (mut game, mut world) = test_game();
+        let keys = [KeyPress::Char('5'); 3];
+        let mut player = Screen { renderer: Buffer::new(MAP_WIDTH, MAP_HEIGHT + PANEL_HEIGHT).with_keys(&keys), options: options() };
+        let mut recorder = Recorder::create(path, &game, &mut player).unwrap();
+        play_game(&mut recorder, &mut game, &mut world);
+
+        // faster, paused and carried on, all while the replay runs
+        let inputs = [KeyPress::Char('+'), KeyPress::Char(' '), KeyPress::Char(' ')];
+        let renderer = Buffer::new(MAP_WIDTH, MAP_HEIGHT + PANEL_HEIGHT).with_keys(&inputs);
+        let mut viewer = Screen { renderer, options: Options { replay_delay: Some(0), ..options() } };
+        watch_replay(&mut viewer, path);
+        std::fs::remove_file(path).unwrap();
+        assert!(viewer.renderer.contains_text("Replay over, 0 inputs not played."));
+    }
+}
@@ -0,0 +1,277 @@
+use crate::mapgen::Generator;
+use crate::templates::Templates;
+use crate::ecs::World;
+use crate::{new_game, Command, Frontend, Game, Stat};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+
+/// Everything `new_game` needs to build the same start again. It is the
+/// first line of a replay file, every line after it is one `Input`.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+pub struct ReplayHeader {
+    pub seed: u64,
+    pub generator: Generator,
+    pub templates: Templates,
+}
+
+/// One answer given to the turn loop.
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+pub enum Input {
+    Command(Command),
+    LevelUp(Option<Stat>),
+}
+
+/// Passes everything another front end answers through to the turn loop,
+/// writing it down as it goes. Lines are flushed one at a time so a crash
+/// still leaves a file that plays up to it.
+pub struct Recorder<'a> {
+    frontend: &'a mut dyn Frontend,
+    file: Option<File>,
+}
+
+impl<'a> Recorder<'a> {
+    /// Starts a new replay file for a game fresh from `new_game`.
+    pub fn create( path: &str, game: &Game, frontend: &'a mut dyn Frontend ) -> io::Result<Self> {
+        let header = ReplayHeader {
+            seed: game.seed,
+            generator: game.generator,
+            templates: game.templates.clone(),
+        };
+        let mut file = File::create(path)?;
+        writeln!(file, "{}", serde_json::to_string(&header)?)?;
+        Ok(Recorder { frontend, file: Some(file) })
+    }
+
+    /// Carries on the replay file of a continued game. Fails if the file is
+    /// missing or was started for a different run.
+    pub fn append( path: &str, game: &Game, frontend: &'a mut dyn Frontend ) -> Result<Self, Box<dyn Error>> {
+        let header = Replay::load(path)?.header;
+        if header.seed != game.seed || header.generator != game.generator {
+            return Err(format!("{} was recorded for a different game", path).into());
+        }
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Recorder { frontend, file: Some(file) })
+    }
+
+    fn record( &mut self, input: Input ) {
+        if let Some(ref mut file) = self.file {
+            let written = serde_json::to_string(&input)
+                .map_err(io::Error::from)
+                .and_then(|line| writeln!(file, "{}", line));
+            if let Err(e) = written {
+                // keep playing, just without the rest of the recording
+                eprintln!("Could not record input, recording stopped: {}", e);
+                self.file = None;
+            }
+        }
+    }
+}
+
+impl<'a> Frontend for Recorder<'a> {
+    fn next_command( &mut self, game: &Game, world: &World ) -> Command {
+        let command = self.frontend.next_command(game, world);
+        // leaving is not part of the run, a continued game picks up after it
+        if command != Command::Exit {
+            self.record(Input::Command(command));
+        }
+        command
+    }
+
+    fn choose_level_up( &mut self, game: &Game, world: &World ) -> Option<Stat> {
+        let choice = self.frontend.choose_level_up(game, world);
+        self.record(Input::LevelUp(choice));
+        choice
+    }
+}
+
+/// A recorded session, to be fed back through `play_game`.
+pub struct Replay {
+    pub header: ReplayHeader,
+    inputs: VecDeque<Input>,
+}
+
+impl Replay {
+    pub fn load( path: &str ) -> Result<Self, Box<dyn Error>> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)?,
+            None => return Err(format!("{} is empty", path).into()),
+        };
+        let mut inputs = VecDeque::new();
+        for (number, line) in lines.enumerate() {
+            let line = line?;
+            // a crash part way through a write leaves a torn last line
+            match serde_json::from_str(&line) {
+                Ok(input) => inputs.push_back(input),
+                Err(e) => {
+                    eprintln!("{}: stopping at line {}: {}", path, number+2, e);
+                    break;
+                }
+            }
+        }
+        Ok(Replay { header, inputs })
+    }
+
+    /// The game as it was when recording started.
+    pub fn new_game( &self ) -> (Game, World) {
+        new_game(self.header.seed, self.header.generator, self.header.templates.clone())
+    }
+
+    /// How many inputs are left to play.
+    pub fn remaining( &self ) -> usize {
+        self.inputs.len()
+    }
+
+    /// The next recorded command, `Exit` once the recording runs out or no
+    /// longer matches what the turn loop asks for.
+    pub fn next_command( &mut self ) -> Command {
+        match self.inputs.pop_front() {
+            Some(Input::Command(command)) => command,
+            Some(input) => {
+                eprintln!("Replay out of step: expected a command, found {:?}", input);
+                self.inputs.clear();
+                Command::Exit
+            }
+            None => Command::Exit,
+        }
+    }
+
+    pub fn next_level_up( &mut self ) -> Option<Stat> {
+        match self.inputs.pop_front() {
+            Some(Input::LevelUp(choice)) => choice,
+            Some(input) => {
+                eprintln!("Replay out of step: expected a level-up, found {:?}", input);
+                self.inputs.clear();
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+impl Frontend for Replay {
+    fn next_command( &mut self, _game: &Game, _world: &World ) -> Command {
+        Replay::next_command(self)
+    }
+
+    fn choose_level_up( &mut self, _game: &Game, _world: &World ) -> Option<Stat> {
+        self.next_level_up()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::WHITE;
+    use crate::pathfinding::first_step;
+    use crate::templates::{ItemTemplate, MonsterTemplate, Transition};
+    use crate::{game_from_save, play_game, Item, PLAYER};
+
+    // harmless monsters worth a level or two each, so a short run levels up,
+    // and healing potions to pick up on the way
+    fn dummies() -> Templates {
+        let always = vec![Transition { level: 1, value: 1 }];
+        Templates {
+            max_room_monsters: vec![Transition { level: 1, value: 6 }],
+            max_room_items: vec![Transition { level: 1, value: 2 }],
+            monsters: vec![MonsterTemplate {
+                name: "training dummy".into(),
+                char: 'd',
+                color: WHITE,
+                hp: 1,
+                defense: 0,
+                power: 0,
+                xp: 500,
+                on_hit: None,
+                chance: always.clone(),
+            }],
+            items: vec![ItemTemplate {
+                name: "healing potion".into(),
+                char: '!',
+                color: WHITE,
+                item: Item::Heal,
+                equipment: None,
+                chance: always,
+            }],
+        }
+    }
+
+    /// Walks at the nearest monster or item for a number of turns, picking up
+    /// whatever it stands on, then plays `then` and exits.
+    struct Hunter {
+        turns: usize,
+        then: VecDeque<Command>,
+        level_ups: usize,
+    }
+
+    impl Hunter {
+        fn new( turns: usize, then: &[Command] ) -> Self {
+            Hunter { turns, then: then.iter().copied().collect(), level_ups: 0 }
+        }
+    }
+
+    impl Frontend for Hunter {
+        fn next_command( &mut self, game: &Game, world: &World ) -> Command {
+            if self.turns == 0 {
+                return self.then.pop_front().unwrap_or(Command::Exit);
+            }
+            self.turns -= 1;
+            let (px, py) = world.positions[PLAYER];
+            if world.positions.at((px, py)).iter().any(|&id| world.items.contains(id)) {
+                return Command::PickUp;
+            }
+            let nearest = world.ais.entities().chain(world.items.entities())
+                .filter_map(|id| world.positions.get(id))
+                .min_by_key(|&&(x, y)| (x - px).abs().max((y - py).abs()));
+            match nearest {
+                Some(&(x, y)) => {
+                    let (dx, dy) = first_step((px, py), (x, y), &game.map, world)
+                        .unwrap_or(((x - px).signum(), (y - py).signum()));
+                    Command::Move { dx, dy }
+                }
+                None => Command::Wait,
+            }
+        }
+
+        fn choose_level_up( &mut self, _game: &Game, _world: &World ) -> Option<Stat> {
+            self.level_ups += 1;
+            Some(Stat::Strength)
+        }
+    }
+
+    #[test]
+    fn a_recorded_and_continued_run_replays_to_the_same_state() {
+        let path = std::env::temp_dir().join(format!("replay-test-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let (mut game, mut world) = new_game(11, Generator::default(), dummies());
+
+        // ends by dropping a potion and then the dagger, which is in an older
+        // slot, so the tile's order is not the order of the slots
+        let mut hunter = Hunter::new(40, &[Command::Drop(1), Command::Drop(0)]);
+        let mut recorder = Recorder::create(path, &game, &mut hunter).unwrap();
+        play_game(&mut recorder, &mut game, &mut world);
+        let dropped: Vec<_> = game.messages.iter().rev().map(|(text, _)| text.as_str()).take(3).collect();
+        assert_eq!(dropped, ["You dropped a dagger.", "Dequipped dagger from main hand.", "You dropped a healing potion."]);
+        // carried on later from the save, as Continue does
+        let save = serde_json::to_string(&(&game, &world)).unwrap();
+        let (mut game, mut world) = game_from_save(&save).unwrap();
+        let mut more = Hunter::new(40, &[]);
+        let mut recorder = Recorder::append(path, &game, &mut more).unwrap();
+        play_game(&mut recorder, &mut game, &mut world);
+        assert!(hunter.level_ups + more.level_ups > 0, "the run should level up");
+
+        let mut replay = Replay::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(replay.header.seed, 11);
+        let (mut replayed_game, mut replayed_world) = replay.new_game();
+        play_game(&mut replay, &mut replayed_game, &mut replayed_world);
+        assert_eq!(replay.remaining(), 0);
+        assert_eq!(
+            serde_json::to_string(&(&replayed_game, &replayed_world)).unwrap(),
+            serde_json::to_string(&(&game, &world)).unwrap(),
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Lasting effects on creatures, the player and monsters alike. Each creature
+//! keeps a list of `Effect`s on the `World`, and `tick_statuses` runs their
+//! per-turn hooks and counts them down once every turn the player takes.
+
+use crate::colors::*;
+use crate::ecs::{Entity, World};
+use crate::{gain_xp, heal, take_damage, Game, PLAYER};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const POISON_DAMAGE: i32 = 1;
+const REGENERATION_AMOUNT: i32 = 1;
+
+// creatures act this many times every two turns, more when hasted, less when slowed
+const NORMAL_SPEED: u64 = 2;
+
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+pub enum Status {
+    Poison,
+    Regeneration,
+    Haste,
+    Slow,
+    Sleep,
+    Confusion,
+    Blindness,
+}
+
+impl Status {
+    /// A short name for the side panel.
+    pub fn label(self) -> &'static str {
+        use Status::*;
+        match self {
+            Poison => "Poison",
+            Regeneration => "Regen",
+            Haste => "Haste",
+            Slow => "Slow",
+            Sleep => "Sleep",
+            Confusion => "Confused",
+            Blindness => "Blind",
+        }
+    }
+
+    pub fn color(self) -> Color {
+        use Status::*;
+        match self {
+            Poison => GREEN,
+            Regeneration => LIGHT_VIOLET,
+            Haste => LIGHT_YELLOW,
+            Slow => LIGHT_BLUE,
+            Sleep => LIGHT_GREY,
+            Confusion => LIGHT_GREEN,
+            Blindness => DARK_GREY,
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Status::*;
+        match *self {
+            Poison => write!(f, "poisoned"),
+            Regeneration => write!(f, "regenerating"),
+            Haste => write!(f, "hasted"),
+            Slow => write!(f, "slowed"),
+            Sleep => write!(f, "asleep"),
+            Confusion => write!(f, "confused"),
+            Blindness => write!(f, "blind"),
+        }
+    }
+}
+
+/// A status with the number of turns it has left.
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Effect {
+    pub status: Status,
+    pub turns: i32,
+}
+
+impl World {
+    pub fn has_status( &self, id: Entity, status: Status ) -> bool {
+        self.statuses.get(id).is_some_and(|effects| effects.iter().any(|e| e.status == status))
+    }
+
+    /// Haste and slow cancel each other out.
+    pub fn speed( &self, id: Entity ) -> u64 {
+        match (self.has_status(id, Status::Haste), self.has_status(id, Status::Slow)) {
+            (true, false) => NORMAL_SPEED * 2,
+            (false, true) => NORMAL_SPEED / 2,
+            _ => NORMAL_SPEED,
+        }
+    }
+
+    /// How often `id` acts during the player's turn number `turn`, spread
+    /// evenly so a creature twice as slow acts every other turn.
+    pub fn actions_in_turn( &self, id: Entity, turn: u64 ) -> u64 {
+        let speed = self.speed(id);
+        let player_speed = self.speed(PLAYER);
+        (turn+1) * speed / player_speed - turn * speed / player_speed
+    }
+}
+
+/// Puts `effect` on a creature. A status it already has keeps whichever
+/// duration is longer rather than stacking.
+pub fn apply_status( id: Entity, effect: Effect, world: &mut World ) {
+    if !world.statuses.contains(id) {
+        world.statuses.insert(id, vec![]);
+    }
+    let effects = &mut world.statuses[id];
+    match effects.iter_mut().find(|e| e.status == effect.status) {
+        Some(existing) => existing.turns = existing.turns.max(effect.turns),
+        None => effects.push(effect),
+    }
+}
+
+/// Like `apply_status`, and says so in the log.
+pub fn afflict( id: Entity, effect: Effect, game: &mut Game, world: &mut World ) {
+    apply_status(id, effect, world);
+    let text = if id == PLAYER {
+        format!("You are {}!", effect.status)
+    } else {
+        format!("The {} is {}!", world.objects[id].name, effect.status)
+    };
+    game.messages.add(text, effect.status.color());
+}
+
+pub fn remove_status( id: Entity, status: Status, world: &mut World ) {
+    if let Some(effects) = world.statuses.get_mut(id) {
+        effects.retain(|e| e.status != status);
+    }
+}
+
+/// Runs every creature's effects for one turn, then counts them down and
+/// says which ones wore off.
+pub fn tick_statuses( game: &mut Game, world: &mut World ) {
+    let affected: Vec<Entity> = world.statuses.entities().collect();
+    for id in affected {
+        let effects = world.statuses[id].clone();
+        for effect in &effects {
+            match effect.status {
+                Status::Poison => {
+                    if let Some(xp) = take_damage(id, POISON_DAMAGE, game, world) {
+                        if id != PLAYER {
+                            gain_xp(PLAYER, xp, world);
+                        }
+                    }
+                }
+                Status::Regeneration => heal(id, REGENERATION_AMOUNT, game, world),
+                _ => {}
+            }
+        }
+        // dying takes the effects with it
+        let effects = match world.statuses.get_mut(id) {
+            Some(effects) => effects,
+            None => continue,
+        };
+        let mut worn_off = vec![];
+        effects.retain_mut(|effect| {
+            effect.turns -= 1;
+            if effect.turns <= 0 {
+                worn_off.push(effect.status);
+            }
+            effect.turns > 0
+        });
+        for status in worn_off {
+            let text = if id == PLAYER {
+                format!("You are no longer {}.", status)
+            } else {
+                format!("The {} is no longer {}!", world.objects[id].name, status)
+            };
+            game.messages.add(text, status.color());
+        }
+        if world.statuses.get(id).is_some_and(|effects| effects.is_empty()) {
+            world.statuses.remove(id);
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use crate::colors::Color;
+use crate::ecs::{Entity, World};
+use crate::status::Effect;
+use crate::{Ai, DeathCallback, Equipment, Fighter, Item, Object, Slot};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+
+// shipped with the game, used when no other templates are given
+const BUILT_IN: &str = include_str!("../data/templates.json");
+
+/// A value that applies from `level` onwards, until a later transition takes over.
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Transition {
+    pub level: u32,
+    pub value: u32,
+}
+
+pub fn from_dungeon_level( table: &[Transition], level: u32 ) -> u32 {
+    table.iter().rev().find(|transition| level >= transition.level).map_or(0, |transition| transition.value)
+}
+
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MonsterTemplate {
+    pub name: String,
+    pub char: char,
+    pub color: Color,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+    pub xp: i32,
+    /// Put on whoever it hurts, such as poison from a bite.
+    #[serde(default)]
+    pub on_hit: Option<Effect>,
+    /// Relative chance of this monster by dungeon level.
+    pub chance: Vec<Transition>,
+}
+
+impl MonsterTemplate {
+    pub fn spawn( &self, world: &mut World, x: i32, y: i32 ) -> Entity {
+        let mut object = Object::new(self.char, &self.name, self.color, true);
+        object.alive = true;
+        let monster = world.spawn(object);
+        world.positions.insert(monster, (x,y));
+        world.fighters.insert(monster, Fighter {
+            base_max_hp: self.hp,
+            hp: self.hp,
+            base_defense: self.defense,
+            base_power: self.power,
+            xp: self.xp,
+            level: 1,
+            on_death: DeathCallback::Monster,
+            on_hit: self.on_hit,
+        });
+        world.ais.insert(monster, Ai::Basic);
+        monster
+    }
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EquipmentTemplate {
+    pub slot: Slot,
+    #[serde(default)]
+    pub max_hp_bonus: i32,
+    #[serde(default)]
+    pub defense_bonus: i32,
+    #[serde(default)]
+    pub power_bonus: i32,
+}
+
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ItemTemplate {
+    pub name: String,
+    pub char: char,
+    pub color: Color,
+    pub item: Item,
+    /// Required for `Equip` items, and only for them.
+    #[serde(default)]
+    pub equipment: Option<EquipmentTemplate>,
+    /// Relative chance of this item by dungeon level.
+    pub chance: Vec<Transition>,
+}
+
+impl ItemTemplate {
+    pub fn spawn( &self, world: &mut World, x: i32, y: i32 ) -> Entity {
+        let item = world.spawn(Object::new(self.char, &self.name, self.color, false));
+        world.positions.insert(item, (x,y));
+        world.items.insert(item, self.item);
+        if let Some(equipment) = self.equipment {
+            world.equipment.insert(item, Equipment {
+                slot: equipment.slot,
+                equipped: false,
+                max_hp_bonus: equipment.max_hp_bonus,
+                defense_bonus: equipment.defense_bonus,
+                power_bonus: equipment.power_bonus,
+            });
+        }
+        item
+    }
+}
+
+/// Everything that can be spawned in the dungeon, and how much of it.
+#[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Templates {
+    pub max_room_monsters: Vec<Transition>,
+    pub max_room_items: Vec<Transition>,
+    pub monsters: Vec<MonsterTemplate>,
+    pub items: Vec<ItemTemplate>,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Templates::from_json(BUILT_IN).expect("the built-in templates are valid")
+    }
+}
+
+/// Every problem found in a set of templates, one per line.
+#[derive(Debug)]
+pub struct TemplateError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.problems.join("\n"))
+    }
+}
+
+impl Error for TemplateError {}
+
+fn check_transitions( table: &[Transition], what: &str, problems: &mut Vec<String> ) {
+    if table.windows(2).any(|pair| pair[0].level >= pair[1].level) {
+        problems.push(format!("{}: levels must be in increasing order", what));
+    }
+}
+
+impl Templates {
+    /// Reads templates from a JSON file, a missing file means the built-in ones.
+    pub fn load( path: &str ) -> Result<Self, Box<dyn Error>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Templates::default()),
+            Err(e) => return Err(e.into()),
+        };
+        Templates::from_json(&contents)
+    }
+
+    pub fn from_json( json: &str ) -> Result<Self, Box<dyn Error>> {
+        let templates: Templates = serde_json::from_str(json)?;
+        templates.validate()?;
+        Ok(templates)
+    }
+
+    /// Checks what the file format alone cannot, such as stats being in range.
+    pub fn validate( &self ) -> Result<(), TemplateError> {
+        let mut problems = vec![];
+        check_transitions(&self.max_room_monsters, "max_room_monsters", &mut problems);
+        check_transitions(&self.max_room_items, "max_room_items", &mut problems);
+
+        for (i, monster) in self.monsters.iter().enumerate() {
+            let what = format!("monsters[{}] \"{}\"", i, monster.name);
+            if monster.name.trim().is_empty() {
+                problems.push(format!("{}: name must not be empty", what));
+            } else if self.monsters[..i].iter().any(|other| other.name == monster.name) {
+                problems.push(format!("{}: name is used by an earlier monster", what));
+            }
+            if monster.char.is_whitespace() || monster.char.is_control() {
+                problems.push(format!("{}: char must be visible", what));
+            }
+            if monster.hp <= 0 {
+                problems.push(format!("{}: hp must be above 0", what));
+            }
+            for (stat, value) in [("defense", monster.defense), ("power", monster.power), ("xp", monster.xp)].iter() {
+                if *value < 0 {
+                    problems.push(format!("{}: {} must not be negative", what, stat));
+                }
+            }
+            if monster.on_hit.is_some_and(|effect| effect.turns <= 0) {
+                problems.push(format!("{}: on_hit turns must be above 0", what));
+            }
+            check_transitions(&monster.chance, &format!("{}: chance", what), &mut problems);
+        }
+
+        for (i, item) in self.items.iter().enumerate() {
+            let what = format!("items[{}] \"{}\"", i, item.name);
+            if item.name.trim().is_empty() {
+                problems.push(format!("{}: name must not be empty", what));
+            } else if self.items[..i].iter().any(|other| other.name == item.name) {
+                problems.push(format!("{}: name is used by an earlier item", what));
+            }
+            if item.char.is_whitespace() || item.char.is_control() {
+                problems.push(format!("{}: char must be visible", what));
+            }
+            match (item.item, item.equipment) {
+                (Item::Equip, None) => {
+                    problems.push(format!("{}: Equip items need an equipment entry", what));
+                }
+                (Item::Equip, Some(_)) | (_, None) => {}
+                (_, Some(_)) => {
+                    problems.push(format!("{}: only Equip items take an equipment entry", what));
+                }
+            }
+            check_transitions(&item.chance, &format!("{}: chance", what), &mut problems);
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(TemplateError { problems })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn built_in() -> Value {
+        serde_json::from_str(BUILT_IN).unwrap()
+    }
+
+    // what loading `json` reports, one problem per line
+    fn problems( json: Value ) -> Vec<String> {
+        let error = Templates::from_json(&json.to_string()).expect_err("the templates should be rejected");
+        error.to_string().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn the_built_in_templates_are_valid() {
+        let templates = Templates::from_json(BUILT_IN).unwrap();
+        assert!(templates.validate().is_ok());
+        assert!(!templates.monsters.is_empty() && !templates.items.is_empty());
+    }
+
+    #[test]
+    fn every_problem_is_reported_on_its_own_line() {
+        let mut json = built_in();
+        let first = json["monsters"][0]["name"].as_str().unwrap().to_string();
+        let second = json["monsters"][1]["name"].as_str().unwrap().to_string();
+        json["monsters"][1]["name"] = json!(first);
+        json["monsters"][0]["hp"] = json!(0);
+        json["max_room_items"] = json!([{ "level": 4, "value": 2 }, { "level": 1, "value": 1 }]);
+        let equip = json["items"].as_array().unwrap().iter().position(|item| item["item"] == "Equip").unwrap();
+        let equip_name = json["items"][equip]["name"].as_str().unwrap().to_string();
+        json["items"][equip].as_object_mut().unwrap().remove("equipment");
+
+        assert_ne!(first, second);
+        assert_eq!(problems(json), [
+            "max_room_items: levels must be in increasing order".to_string(),
+            format!("monsters[0] \"{}\": hp must be above 0", first),
+            format!("monsters[1] \"{}\": name is used by an earlier monster", first),
+            format!("items[{}] \"{}\": Equip items need an equipment entry", equip, equip_name),
+        ]);
+    }
+
+    #[test]
+    fn chances_out_of_order_name_their_template() {
+        let mut json = built_in();
+        let name = json["monsters"][0]["name"].as_str().unwrap().to_string();
+        json["monsters"][0]["chance"] = json!([{ "level": 3, "value": 10 }, { "level": 3, "value": 20 }]);
+        assert_eq!(problems(json), [format!("monsters[0] \"{}\": chance: levels must be in increasing order", name)]);
+    }
+
+    #[test]
+    fn unknown_fields_are_named() {
+        let mut json = built_in();
+        json["monsters"][0]["speed"] = json!(2);
+        let problems = problems(json);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("unknown field `speed`"), "{}", problems[0]);
+    }
+}